@@ -1,14 +1,15 @@
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use std::{io::Read, io::Write, time::{Duration, Instant}};
 use tracing_subscriber::filter::EnvFilter;
 
-fn run_client_mode(args: Args) { 
+fn run_client_mode(args: Args, config: bond_tcp::BondConfig) { 
 
-    let mut stream = bond_tcp::BondTcpStream::connect(args.addr.clone()).unwrap();
+    let mut stream = bond_tcp::BondTcpStream::connect_with(args.addr.clone(), &config).unwrap();
     println!("Connected successfully to {}", args.addr);
     
     let mut buf = vec![0u8; args.size];
     buf.fill(42);    
+    let mut sampler = Sampler::new("tx".into(), Duration::from_secs(args.period));
 
     loop {
         let n = stream.write(&buf).unwrap();
@@ -16,21 +17,56 @@ fn run_client_mode(args: Args) {
             println!("Connectio closed by remote peer");
             break;
         }        
+        sampler.sample(n, stream.io_counters());
     }    
 }
 
-fn run_server_mode(args: Args) { 
+/// Periodically reports the throughput, as seen by the application and on the
+/// wire, along with the number of system calls issued by the bond, normalised
+/// per transferred megabyte.
+struct Sampler {
+    label: String,
+    period: Duration,
+    start: Instant,
+    bytes: usize,
+    counters: bond_tcp::IoCounters,
+}
+
+impl Sampler {
+    fn new(label: String, period: Duration) -> Self {
+        Sampler { label, period, start: Instant::now(), bytes: 0, counters: Default::default() }
+    }
+
+    fn sample(&mut self, n: usize, counters: bond_tcp::IoCounters) {
+        self.bytes += n;
+        let delta = self.start.elapsed();
+        if delta >= self.period {
+            let throughput = ((self.bytes * 8) as f32 / delta.as_secs_f32()) / (10u64.pow(6) as f32);
+            let reads = counters.reads - self.counters.reads;
+            let writes = counters.writes - self.counters.writes;
+            let polls = counters.polls - self.counters.polls;
+            let rearms = counters.rearms - self.counters.rearms;
+            let syscalls = counters.syscalls - self.counters.syscalls;
+            let wire = ((counters.wire_bytes - self.counters.wire_bytes) * 8) as f32 / delta.as_secs_f32() / (10u64.pow(6) as f32);
+            let mbytes = self.bytes as f32 / (1024 * 1024) as f32;
+            let per_mb = syscalls as f32 / mbytes;
+            println!("[{}]: {throughput} Mbps ({wire} Mbps on the wire), {per_mb:.1} syscalls/MB (read: {reads}, write: {writes}, poll: {polls}, rearm: {rearms})", self.label);
+            self.start = Instant::now();
+            self.bytes = 0;
+            self.counters = counters;
+        }
+    }
+}
+
+fn run_server_mode(args: Args, config: bond_tcp::BondConfig) { 
     
-    let mut listener = bond_tcp::BondTcpListener::bind(args.addr, args.bond).unwrap();
+    let mut listener = bond_tcp::BondTcpListener::bind_with(args.addr, args.bond, config).unwrap();
     let mut sid = 0;
     loop {
         if let Ok((mut stream, addr)) =  listener.accept() {
             println!("Accepted connection from: {addr}");
             let mut buf = vec![0u8; args.size];
-            let mut start = Instant::now();
-            let mut total_recv = 0;
-            let sampling_period = Duration::from_secs(args.period);
-            let cid = sid;
+            let mut sampler = Sampler::new(sid.to_string(), Duration::from_secs(args.period));
             sid += 1;
             std::thread::spawn(move || {
             loop {            
@@ -40,14 +76,7 @@ fn run_server_mode(args: Args) {
                     println!("Socket close from remote party...");
                     break; 
                 }          
-                total_recv += n;
-                let delta = start.elapsed();
-                if  delta >= sampling_period {
-                    let throughput = ((total_recv * 8) as f32 / delta.as_secs_f32()) / ( 10u64.pow(6) as f32);                    
-                    println!("[{cid}]: {throughput} Mbps");
-                    start = Instant::now();
-                    total_recv = 0;
-                }
+                sampler.sample(n, stream.io_counters());
             }});
         } else {
             println!("Failed to accept connection!");
@@ -67,24 +96,54 @@ fn init_env_filter(env_filter: EnvFilter) {
     let _ = tracing::subscriber::set_global_default(subscriber);
 }
 
-fn main() { 
-
-     tracing_log::LogTracer::init().expect("Failed to set logger");
-    
+fn main() {
     // Initialize tracing subscriber
-     match EnvFilter::try_from_default_env() {
-        Ok(env_filter) => init_env_filter(env_filter),
-        _ => { }
+     if let Ok(env_filter) = EnvFilter::try_from_default_env() {
+        init_env_filter(env_filter);
      }
 
     let args = Args::parse();
+    #[cfg_attr(not(feature = "metrics"), allow(unused_mut))]
+    let mut config = args.config();
+    if let Some(addr) = args.metrics_addr {
+        #[cfg(feature = "metrics")]
+        {
+            config.metrics = Some(serve_metrics(addr));
+        }
+        #[cfg(not(feature = "metrics"))]
+        panic!("bperf was built without the metrics feature, cannot serve metrics on {addr}");
+    }
     if args.client {
-        run_client_mode(args);
+        run_client_mode(args, config);
     } else {
-        run_server_mode(args);
+        run_server_mode(args, config);
     }
 }
 
+/// Serves the metrics of the bonds over HTTP on the given address, whatever
+/// the path asked for, from a thread of its own.
+#[cfg(feature = "metrics")]
+fn serve_metrics(addr: std::net::SocketAddr) -> bond_tcp::Metrics {
+    let metrics = bond_tcp::Metrics::new();
+    let listener = std::net::TcpListener::bind(addr).unwrap();
+    println!("Serving metrics on http://{addr}/metrics");
+    let served = metrics.clone();
+    std::thread::spawn(move || {
+        for mut conn in listener.incoming().flatten() {
+            // The request is not looked at, reading it only spares the client a reset.
+            let mut request = [0u8; 1024];
+            let _ = conn.read(&mut request);
+            let body = served.render();
+            let _ = write!(
+                conn,
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            );
+        }
+    });
+    metrics
+}
+
 /// The performance benchmarking application for BondSocket
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
@@ -104,4 +163,98 @@ struct Args {
     /// The sampling period
     #[arg(short, long, default_value = "1")]
     period: u64,
+    /// Drive the substreams from a background I/O thread, using queues of the given size
+    #[arg(short, long)]
+    queue: Option<usize>,
+    /// Register the substreams in oneshot mode, re-arming them before every wait, as the baseline to compare the syscalls/MB of the edge-triggered poller with
+    #[arg(long)]
+    oneshot: bool,
+    /// The mechanism used to perform substream I/O
+    #[arg(long, value_enum, default_value_t = BackendArg::Poll)]
+    backend: BackendArg,
+    /// Ask for a CRC32C on every frame
+    #[arg(long)]
+    crc: bool,
+    /// The compression asked for on the payload of frames
+    #[arg(long, value_enum, default_value_t = CompressionArg::None)]
+    compress: CompressionArg,
+    /// Encrypt frames with the given pre-shared key, made of 64 hexadecimal digits
+    #[arg(long, value_parser = parse_psk)]
+    psk: Option<bond_tcp::PreSharedKey>,
+    /// Bind the substreams to the given local address, repeated to spread them over several ones
+    #[arg(long)]
+    bind: Vec<std::net::SocketAddr>,
+    /// Use the given congestion control algorithm, repeated to mix several ones across the substreams
+    #[arg(long)]
+    congestion: Vec<String>,
+    /// Serve the metrics of the bonds in the Prometheus text format over HTTP on the given address
+    #[arg(long)]
+    metrics_addr: Option<std::net::SocketAddr>,
+}
+
+fn parse_psk(hex: &str) -> Result<bond_tcp::PreSharedKey, String> {
+    if hex.len() != 64 || !hex.is_ascii() {
+        return Err("expected 64 hexadecimal digits".into());
+    }
+    let mut key = [0u8; 32];
+    for (i, b) in key.iter_mut().enumerate() {
+        *b = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).map_err(|e| e.to_string())?;
+    }
+    Ok(bond_tcp::PreSharedKey::new(key))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum CompressionArg {
+    None,
+    Lz4,
+    Zstd,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum BackendArg {
+    Poll,
+    IoUring,
+}
+
+impl Args {
+    fn config(&self) -> bond_tcp::BondConfig {
+        let io_mode = match self.queue {
+            Some(queue_size) => bond_tcp::IoMode::Background { queue_size },
+            None => bond_tcp::IoMode::Inline,
+        };
+        let backend = match self.backend {
+            BackendArg::Poll => bond_tcp::Backend::Poll,
+            #[cfg(all(target_os = "linux", feature = "io-uring"))]
+            BackendArg::IoUring => bond_tcp::Backend::IoUring,
+            #[cfg(not(all(target_os = "linux", feature = "io-uring")))]
+            BackendArg::IoUring => panic!("bperf was built without the io-uring feature"),
+        };
+        let checksum = if self.crc { bond_tcp::Checksum::Crc32c } else { bond_tcp::Checksum::None };
+        let compression = match self.compress {
+            CompressionArg::None => bond_tcp::Compression::None,
+            #[cfg(feature = "lz4")]
+            CompressionArg::Lz4 => bond_tcp::Compression::Lz4,
+            #[cfg(not(feature = "lz4"))]
+            CompressionArg::Lz4 => panic!("bperf was built without the lz4 feature"),
+            #[cfg(feature = "zstd")]
+            CompressionArg::Zstd => bond_tcp::Compression::Zstd,
+            #[cfg(not(feature = "zstd"))]
+            CompressionArg::Zstd => panic!("bperf was built without the zstd feature"),
+        };
+        bond_tcp::BondConfig {
+            io_mode,
+            oneshot: self.oneshot,
+            backend,
+            checksum,
+            compression,
+            psk: self.psk.clone(),
+            local_addrs: self.bind.clone(),
+            socket_options: self
+                .congestion
+                .iter()
+                .map(|c| bond_tcp::SocketOptions { congestion: Some(c.clone()), ..Default::default() })
+                .collect(),
+            ..Default::default()
+        }
+    }
 }
\ No newline at end of file
@@ -17,9 +17,6 @@ fn init_env_filter(env_filter: EnvFilter) {
 }
 
 fn main() -> std::io::Result<()> {
-    // Initialize log bridge to capture log crate messages - MUST be first!
-    tracing_log::LogTracer::init().expect("Failed to set logger");
-    
     // Initialize tracing subscriber
     match EnvFilter::try_from_default_env() {
         Ok(env_filter) => init_env_filter(env_filter),
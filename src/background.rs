@@ -0,0 +1,360 @@
+use std::io::{IoSlice, IoSliceMut, Result as IoResult};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+
+use std::time::Duration;
+
+use crate::bond::{copy_to_slices, Bond, IoCounters, Substream, FRAGMENT_SIZE};
+use crate::error::BondError;
+use crate::stats::SubstreamStats;
+use crate::trace::io_trace;
+use crate::transport::Transport;
+
+/// The application side of a bond driven by a background I/O thread.
+///
+/// `read` and `write` only move data to and from a pair of bounded queues
+/// shared with the I/O thread, blocking when the receive queue is empty or
+/// the transmission queue is full. Each queue is swapped out as a whole by
+/// its consumer, so bytes are copied once in each direction.
+pub(crate) struct Background {
+    shared: Arc<Shared>,
+    poller: Arc<polling::Poller>,
+    rx: Vec<u8>,
+    pos: usize,
+    /// Whether frames are numbered, which writes sending copies of them need.
+    pub(crate) numbered: bool,
+}
+
+struct Shared {
+    queues: Mutex<Queues>,
+    cond: Condvar,
+}
+
+struct Queues {
+    tx: Vec<u8>,
+    /// The end of each run of bytes of `tx` written asking for the same
+    /// number of copies of their frames, `None` for the bond's own.
+    copies: Vec<(usize, Option<usize>)>,
+    rx: Vec<u8>,
+    capacity: usize,
+    /// Whether the I/O thread has written everything it took from `tx`.
+    tx_idle: bool,
+    eof: bool,
+    error: Option<std::io::Error>,
+    closed: bool,
+    counters: IoCounters,
+    wait_time: Duration,
+    substreams: Vec<SubstreamStats>,
+}
+
+impl Queues {
+    /// Returns the error the I/O thread failed with, every time it is asked
+    /// for, the bond errors being cloned as they are.
+    fn error(&self) -> Option<std::io::Error> {
+        self.error.as_ref().map(|e| match BondError::of(e) {
+            Some(b) => b.clone().into(),
+            None => std::io::Error::new(e.kind(), e.to_string()),
+        })
+    }
+
+    /// Copies the counters of the bond, for the application to read them.
+    fn record<T: Transport>(&mut self, bond: &Bond<T>) {
+        self.counters = bond.counters;
+        self.wait_time = bond.wait_time;
+        self.substreams = bond.substream_stats();
+    }
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, Queues> {
+        self.queues.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn wait<'a>(&self, q: MutexGuard<'a, Queues>) -> MutexGuard<'a, Queues> {
+        self.cond.wait(q).unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Background {
+    /// Hands the bond over to a new I/O thread. Queues are never smaller than
+    /// a fragment, to keep the I/O thread from sending tiny frames.
+    pub(crate) fn spawn<T: Transport>(bond: Bond<T>, queue_size: usize) -> IoResult<Background> {
+        let capacity = std::cmp::max(queue_size, FRAGMENT_SIZE);
+        let shared = Arc::new(Shared {
+            queues: Mutex::new(Queues {
+                tx: Vec::with_capacity(capacity),
+                copies: Vec::new(),
+                rx: Vec::with_capacity(capacity),
+                capacity,
+                tx_idle: true,
+                eof: false,
+                error: None,
+                closed: false,
+                counters: IoCounters::default(),
+                wait_time: Duration::ZERO,
+                substreams: vec![SubstreamStats::default(); bond.substreams.len()],
+            }),
+            cond: Condvar::new(),
+        });
+        let poller = bond.poller.clone();
+        let numbered = bond.numbered;
+        let io = IoThread { bond, shared: shared.clone(), tx: Vec::with_capacity(capacity), copies: Vec::new(), tx_pos: 0 };
+        std::thread::Builder::new()
+            .name("bond-io".into())
+            .spawn(move || io.run())?;
+        Ok(Background { shared, poller, rx: Vec::with_capacity(capacity), pos: 0, numbered })
+    }
+
+    pub(crate) fn io_counters(&self) -> IoCounters {
+        self.shared.lock().counters
+    }
+
+    /// Returns the counters of the bond, the time the I/O thread spent
+    /// waiting and the stats of the substreams, but for their TCP state.
+    pub(crate) fn traffic(&self) -> (IoCounters, Duration, Vec<SubstreamStats>) {
+        let q = self.shared.lock();
+        (q.counters, q.wait_time, q.substreams.clone())
+    }
+
+    /// Queues the given buffers for transmission, each frame carrying them to
+    /// be sent on `copies` substreams, or as many as the bond sends them on if
+    /// `None`.
+    pub(crate) fn write_vectored(&mut self, bufs: &[IoSlice<'_>], copies: Option<usize>) -> IoResult<usize> {
+        let len: usize = bufs.iter().map(|b| b.len()).sum();
+        if len == 0 {
+            return Ok(0);
+        }
+        let mut q = self.shared.lock();
+        loop {
+            if let Some(e) = q.error() {
+                return Err(e);
+            }
+            if q.tx.len() < q.capacity {
+                break;
+            }
+            io_trace!("Transmission queue full, waiting");
+            q = self.shared.wait(q);
+        }
+        let was_empty = q.tx.is_empty();
+        let space = q.capacity - q.tx.len();
+        let mut n = 0;
+        for b in bufs.iter() {
+            let k = std::cmp::min(b.len(), space - n);
+            q.tx.extend_from_slice(&b[..k]);
+            n += k;
+            if n == space {
+                break;
+            }
+        }
+        let end = q.tx.len();
+        match q.copies.last_mut() {
+            Some(run) if run.1 == copies => run.0 = end,
+            _ => q.copies.push((end, copies)),
+        }
+        q.tx_idle = false;
+        drop(q);
+        // The I/O thread picks up a non empty queue on its own once done with
+        // the previous one, it only needs waking up on the first bytes.
+        if was_empty {
+            self.poller.notify()?;
+        }
+        io_trace!(len = n, "Queued for transmission");
+        Ok(n)
+    }
+
+    pub(crate) fn flush(&mut self) -> IoResult<()> {
+        let mut q = self.shared.lock();
+        loop {
+            if let Some(e) = q.error() {
+                return Err(e);
+            }
+            if q.tx.is_empty() && q.tx_idle {
+                return Ok(());
+            }
+            q = self.shared.wait(q);
+        }
+    }
+
+    pub(crate) fn fill_buf(&mut self) -> IoResult<&[u8]> {
+        if self.pos == self.rx.len() {
+            let mut q = self.shared.lock();
+            loop {
+                if !q.rx.is_empty() {
+                    break;
+                }
+                if q.eof {
+                    return Ok(&[]);
+                }
+                if let Some(e) = q.error() {
+                    return Err(e);
+                }
+                q = self.shared.wait(q);
+            }
+            self.rx.clear();
+            self.pos = 0;
+            std::mem::swap(&mut self.rx, &mut q.rx);
+            drop(q);
+            self.poller.notify()?;
+        }
+        Ok(&self.rx[self.pos..])
+    }
+
+    pub(crate) fn consume(&mut self, amt: usize) {
+        self.pos = std::cmp::min(self.pos + amt, self.rx.len());
+    }
+
+    pub(crate) fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> IoResult<usize> {
+        let len: usize = bufs.iter().map(|b| b.len()).sum();
+        let mut bufs = bufs;
+        IoSliceMut::advance_slices(&mut bufs, 0);
+        let mut n = 0;
+        while n < len {
+            let data = self.fill_buf()?;
+            if data.is_empty() {
+                return Ok(n);
+            }
+            let copied = copy_to_slices(data, &mut bufs);
+            self.consume(copied);
+            n += copied;
+        }
+        Ok(len)
+    }
+}
+
+impl Drop for Background {
+    fn drop(&mut self) {
+        // The I/O thread exits, closing the substreams, once it has written
+        // what is left in the transmission queue.
+        self.shared.lock().closed = true;
+        self.shared.cond.notify_all();
+        let _ = self.poller.notify();
+    }
+}
+
+/// The I/O thread, owning the bond and the transmission queue last swapped
+/// out of the shared state.
+struct IoThread<T: Transport> {
+    bond: Bond<T>,
+    shared: Arc<Shared>,
+    tx: Vec<u8>,
+    copies: Vec<(usize, Option<usize>)>,
+    tx_pos: usize,
+}
+
+impl<T: Transport> IoThread<T> {
+    fn run(mut self) {
+        let span = self.bond.span.clone();
+        let _entered = span.enter();
+        tracing::debug!(substreams = self.bond.substreams.len(), "Background I/O thread started");
+        // A panic is handed to the application like any other failure,
+        // rather than leaving it blocked on queues no one drives anymore.
+        let e = match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| self.drive())) {
+            Ok(Ok(())) => {
+                tracing::debug!("Background I/O thread done");
+                return;
+            }
+            Ok(Err(e)) => e,
+            Err(panic) => std::io::Error::other(format!("Background I/O thread panicked: {}", panic_message(&*panic))),
+        };
+        tracing::debug!(error = %e, "Background I/O thread failed");
+        let mut q = self.shared.lock();
+        q.error = Some(e);
+        q.record(&self.bond);
+        drop(q);
+        self.shared.cond.notify_all();
+    }
+
+    fn drive(&mut self) -> IoResult<()> {
+        loop {
+            let (mut progress, done) = self.exchange()?;
+            if done {
+                return Ok(());
+            }
+            progress |= self.schedule();
+            // Control frames fall due while the thread is kept busy as well.
+            self.bond.send_control()?;
+            for id in 0..self.bond.substreams.len() {
+                progress |= self.bond.try_flush_tx(id)?;
+                let s = &self.bond.substreams[id];
+                if !s.eof && !s.rx.is_full() && s.readable {
+                    progress |= self.bond.try_fill_rx(id)?.is_some();
+                }
+            }
+            if !progress {
+                self.bond.wait(None)?;
+            }
+        }
+    }
+
+    fn tx_idle(&self) -> bool {
+        self.tx_pos == self.tx.len() && self.bond.substreams.iter().all(Substream::is_flushed)
+    }
+
+    /// Takes the transmission queue from the application and hands it the
+    /// payload received in order. Returns whether anything was moved and
+    /// whether the thread is done.
+    fn exchange(&mut self) -> IoResult<(bool, bool)> {
+        let mut q = self.shared.lock();
+        let mut progress = false;
+        if self.tx_pos == self.tx.len() && !q.tx.is_empty() {
+            self.tx.clear();
+            self.copies.clear();
+            self.tx_pos = 0;
+            std::mem::swap(&mut self.tx, &mut q.tx);
+            std::mem::swap(&mut self.copies, &mut q.copies);
+            progress = true;
+        }
+        while q.rx.len() < q.capacity {
+            let n = self.bond.ready_payload()?;
+            if n == 0 {
+                break;
+            }
+            let k = std::cmp::min(n, q.capacity - q.rx.len());
+            q.rx.extend_from_slice(&self.bond.payload()[..k]);
+            self.bond.consume(k);
+            progress = true;
+        }
+        if !q.eof && self.bond.closed() && self.bond.ready_payload()? == 0 {
+            tracing::debug!(substream = self.bond.rx_stream, "Substream closed, no more frames to receive");
+            q.eof = true;
+            progress = true;
+        }
+        let idle = self.tx_idle() && q.tx.is_empty();
+        progress |= idle != q.tx_idle;
+        q.tx_idle = idle;
+        q.record(&self.bond);
+        let done = q.closed && idle;
+        drop(q);
+        if progress {
+            self.shared.cond.notify_all();
+        }
+        Ok((progress, done))
+    }
+
+    /// Cuts the transmission queue into frames and schedules them on the
+    /// substreams, round-robin or by latency, as long as they have room for
+    /// them. A substream that stalls only blocks the writer once the frames
+    /// queued behind the one it could not take have filled the transmission
+    /// queue. Frames do not straddle writes asking for different numbers of
+    /// copies.
+    fn schedule(&mut self) -> bool {
+        let mut progress = false;
+        while self.tx_pos < self.tx.len() {
+            let &(end, copies) = self.copies.iter().find(|(end, _)| *end > self.tx_pos).expect("queued bytes belong to a run");
+            let flen = std::cmp::min(FRAGMENT_SIZE, end - self.tx_pos);
+            if !self.bond.push_frame(&[IoSlice::new(&self.tx[self.tx_pos..self.tx_pos + flen])], copies) {
+                break;
+            }
+            self.tx_pos += flen;
+            progress = true;
+        }
+        progress
+    }
+}
+
+/// Returns the message a thread panicked with.
+fn panic_message(panic: &(dyn std::any::Any + Send)) -> &str {
+    match panic.downcast_ref::<&str>() {
+        Some(message) => message,
+        None => panic.downcast_ref::<String>().map_or("unknown cause", String::as_str),
+    }
+}
@@ -0,0 +1,1733 @@
+use std::io::{IoSlice, IoSliceMut, Read, Result as IoResult, Write};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tracing::{field, Span};
+use uuid::Uuid;
+
+use crate::compress::{Deflater, Inflater};
+use crate::config::{Backend, BondConfig, Checksum, Compression, Heartbeat, IoMode, Scheduler};
+use crate::crypto::{Opener, Sealer, DATA_SPACE, TAG_SIZE};
+use crate::error::BondError;
+use crate::events::{self, BondEventKind, Observer};
+use crate::handshake::Session;
+use crate::link::Link;
+#[cfg(feature = "metrics")]
+use crate::metrics::{BondMetrics, SubstreamMetrics};
+use crate::stats::{RttEstimate, SubstreamCounters, SubstreamStats};
+use crate::trace::io_trace;
+use crate::transport::Transport;
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+use crate::uring::Uring;
+
+pub(crate) const FRAGMENT_SIZE: usize = 8*1024;
+const FRAME_LEN_SIZE: usize = size_of::<u32>();
+/// The size of the sequence number following the length of numbered frames.
+const FRAME_SEQ_SIZE: usize = size_of::<u64>();
+/// Set in the length of frames whose payload is compressed.
+const FRAME_COMPRESSED: u32 = 1 << 31;
+/// Set in the length of control frames, which are exchanged by the bonds
+/// themselves rather than carrying payload. They are neither compressed nor
+/// numbered, and may be sent on any substream between two frames, the first
+/// byte of their body telling what they are. When frames are encrypted, the
+/// body of control frames is too, each substream having its own nonce space
+/// in which they are counted.
+const FRAME_CONTROL: u32 = 1 << 30;
+const FRAME_FLAGS: u32 = FRAME_COMPRESSED | FRAME_CONTROL;
+/// The largest body of a control frame.
+const MAX_CONTROL_SIZE: usize = 64;
+/// A control frame telling that the peer is alive, sent on the substreams
+/// that would otherwise be idle.
+const CONTROL_HEARTBEAT: u8 = 0;
+/// A control frame asking the peer to echo the timestamp it carries, in
+/// microseconds since the bond was formed as a little-endian `u64`, on the
+/// same substream.
+const CONTROL_PING: u8 = 1;
+/// A control frame echoing the timestamp of the last ping received on the
+/// substream, followed by the number of bytes read from the substream so far
+/// as a little-endian `u64`, which tell the round-trip time of the substream
+/// and how much of what was sent on it was delivered.
+const CONTROL_PONG: u8 = 2;
+/// The body of pings: their kind followed by a timestamp.
+const PING_SIZE: usize = 1 + size_of::<u64>();
+/// The body of pongs: their kind followed by a timestamp and a byte count.
+const PONG_SIZE: usize = PING_SIZE + size_of::<u64>();
+/// The interval of the pings measuring the substreams when frames are
+/// scheduled by latency and no interval is configured.
+const DEFAULT_PING_INTERVAL: Duration = Duration::from_millis(100);
+/// The fewest heartbeat intervals a substream stays silent before it is given
+/// up on: a peer busy with the other substreams may leave one of them without
+/// anything for up to twice the interval.
+const MIN_MISSES: u32 = 3;
+/// The largest frame header: the payload length, followed by the sequence
+/// number of the frame when frames are numbered and, when frames are
+/// checked, by the CRC32C of all of them and of the payload.
+const MAX_FRAME_HEADER_SIZE: usize = FRAME_LEN_SIZE + FRAME_SEQ_SIZE + size_of::<u32>();
+const RX_BUFFER_SIZE: usize = 64*1024;
+/// Bound on the frames scheduled on a single substream and not yet written to
+/// its socket, when frames are not written straight from the caller's buffers.
+const TX_BUFFER_SIZE: usize = 64*1024;
+/// Room for a few control frames, which are scheduled even when the frames
+/// are written straight from the caller's buffers: a pong and a ping, along
+/// with a heartbeat.
+const CONTROL_BUFFER_SIZE: usize = 3 * (MAX_FRAME_HEADER_SIZE + MAX_CONTROL_SIZE);
+
+/// Counters of the system calls issued by a `BondTcpStream`, and of the bytes
+/// it moved.
+///
+/// These are meant for benchmarking the cost of the I/O path, e.g. to compute
+/// the number of system calls per transferred megabyte.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IoCounters {
+    /// Number of `read` calls issued on the substreams.
+    pub reads: u64,
+    /// Number of `write` calls issued on the substreams.
+    pub writes: u64,
+    /// Number of times the stream blocked waiting on the poller.
+    pub polls: u64,
+    /// Number of times a substream was re-armed with the poller before
+    /// waiting, which only happens when it is registered in oneshot mode.
+    pub rearms: u64,
+    /// Number of system calls issued on the I/O path. With the poll backend
+    /// this is the sum of the above, while with io_uring reads and writes are
+    /// submitted in batches and each wait for their completion is one call.
+    pub syscalls: u64,
+    /// Number of payload bytes sent and received, as written and read by the
+    /// application.
+    pub payload_bytes: u64,
+    /// Number of bytes of the frames sent and received, headers included and
+    /// payloads as carried by the substreams, possibly compressed.
+    pub wire_bytes: u64,
+}
+
+/// One of the TCP connections making up a bond, together with the readiness
+/// last reported for it by the poller.
+///
+/// Substreams are registered once, for both directions, in edge-triggered mode.
+/// A flag is only cleared when the corresponding operation hits `WouldBlock`,
+/// and it is set again when the poller reports a new edge, thus readiness
+/// observed for a substream while waiting on another one is never lost.
+pub(crate) struct Substream<T> {
+    pub(crate) link: Link<T>,
+    pub(crate) readable: bool,
+    pub(crate) writable: bool,
+    pub(crate) eof: bool,
+    pub(crate) rx: Buffer,
+    pub(crate) tx: Buffer,
+    /// The frames carried so far, those received locating corrupted frames
+    /// within the substream.
+    pub(crate) counters: SubstreamCounters,
+    /// When the substream last stopped taking frames, and whether it was
+    /// reported stalled since.
+    blocked_since: Option<Instant>,
+    stalled: bool,
+    /// The address of the peer, looked up once the bond is formed as the
+    /// socket may no longer tell it once failed.
+    peer: Option<SocketAddr>,
+    /// When a frame was last handed to the substream and when data was last
+    /// received on it, which tell when a heartbeat is due and for how long
+    /// the peer has been silent, whether it was reported stalled for being
+    /// silent since, no frame being scheduled on it meanwhile, and whether
+    /// it was given up on for staying silent past the miss threshold,
+    /// nothing being sent on it anymore.
+    last_sent: Instant,
+    last_received: Instant,
+    silent: bool,
+    lost: bool,
+    /// When a ping was last scheduled, the timestamp of the last ping
+    /// received and not answered yet, and the round-trip time measured out
+    /// of the pongs.
+    last_ping: Instant,
+    pong: Option<u64>,
+    pub(crate) rtt: RttEstimate,
+    /// The control frames sent and received, from which their nonces are
+    /// derived when frames are encrypted.
+    control_sent: u64,
+    control_received: u64,
+    /// The bytes handed to the substream, control frames included, and read
+    /// from it, along with the bytes the peer had read as of its last pong,
+    /// when it came and what had been written by then, and the delivery rate
+    /// measured out of the pongs in bytes per second. They predict when a
+    /// frame handed to the substream reaches the peer.
+    written: u64,
+    read: u64,
+    acked: u64,
+    last_pong: Option<(Instant, u64)>,
+    rate: Option<f64>,
+    /// The span of the substream, within the one of its bond.
+    span: Span,
+    #[cfg(feature = "metrics")]
+    metrics: Option<Arc<SubstreamMetrics>>,
+}
+
+impl<T> Substream<T> {
+    /// Appends `n` bytes received from the socket to the receive buffer.
+    pub(crate) fn filled(&mut self, n: usize) {
+        self.rx.commit(n);
+        self.read += n as u64;
+        self.heard();
+    }
+
+    /// Records that the peer had read `acked` bytes from the substream when
+    /// it answered a ping. The delivery rate is only measured while the
+    /// substream is kept busy, i.e. when the peer had not yet read all that
+    /// was written by the time of the previous pong.
+    fn acknowledged(&mut self, now: Instant, acked: u64) {
+        let acked = acked.clamp(self.acked, self.written);
+        if let Some((then, written)) = self.last_pong
+            && acked > self.acked
+            && acked < written
+            && now > then
+        {
+            let sample = (acked - self.acked) as f64 / now.duration_since(then).as_secs_f64();
+            self.rate = Some(self.rate.map_or(sample, |rate| (7.0 * rate + sample) / 8.0));
+        }
+        self.acked = acked;
+        self.last_pong = Some((now, self.written));
+    }
+
+    /// Predicts how long a frame of `len` bytes handed to the substream takes
+    /// to reach the peer: half the round-trip time, plus the time it takes
+    /// to deliver the bytes ahead of it, those the peer had not read as of
+    /// its last pong and was not expected to read since. Returns `None`
+    /// until a ping was answered.
+    fn arrival(&self, now: Instant, len: usize) -> Option<Duration> {
+        let rtt = self.rtt.get()?.smoothed;
+        // Until it is measured, the delivery rate is that of a window of a
+        // transmission buffer every round trip.
+        let rate = self.rate.unwrap_or(TX_BUFFER_SIZE as f64 / rtt.as_secs_f64().max(1e-6));
+        let since = self.last_pong.map_or(0.0, |(then, _)| now.duration_since(then).as_secs_f64());
+        let backlog = ((self.written - self.acked) as f64 - rate * since).max(0.0) + len as f64;
+        Some(rtt / 2 + Duration::from_secs_f64(backlog / rate))
+    }
+
+    /// Records that the peer was heard from on the substream.
+    fn heard(&mut self) {
+        self.last_received = Instant::now();
+        self.silent = false;
+    }
+}
+
+impl<T: Transport> Substream<T> {
+    /// Records that the socket cannot take more data until it is reported
+    /// writable again.
+    fn blocked(&mut self) {
+        self.writable = false;
+        self.blocked_since.get_or_insert_with(Instant::now);
+    }
+
+    /// Records that the socket took data, ending any stall.
+    fn unblocked(&mut self) {
+        self.blocked_since = None;
+        self.stalled = false;
+    }
+
+    /// Counts a frame of `wire` bytes handed to the substream.
+    fn sent(&mut self, wire: u64) {
+        self.last_sent = Instant::now();
+        self.written += wire;
+        self.counters.frames_sent += 1;
+        self.counters.bytes_sent += wire;
+        #[cfg(feature = "metrics")]
+        if let Some(m) = self.metrics.as_ref() {
+            m.bytes_sent.fetch_add(wire, std::sync::atomic::Ordering::Relaxed);
+        }
+    }
+
+    /// Counts a frame of `wire` bytes received on the substream.
+    fn received(&mut self, wire: u64) {
+        self.counters.frames_received += 1;
+        self.counters.bytes_received += wire;
+        #[cfg(feature = "metrics")]
+        if let Some(m) = self.metrics.as_ref() {
+            m.bytes_received.fetch_add(wire, std::sync::atomic::Ordering::Relaxed);
+        }
+    }
+
+    /// Returns whether everything scheduled on the substream, including the
+    /// TLS records of frames already taken, has been written to the socket.
+    pub(crate) fn is_flushed(&self) -> bool {
+        self.tx.is_empty() && !self.link.has_records()
+    }
+}
+
+/// The header of an outgoing frame.
+pub(crate) struct FrameHeader {
+    bytes: [u8; MAX_FRAME_HEADER_SIZE],
+    len: usize,
+}
+
+impl FrameHeader {
+    /// Returns the header of a frame carrying the given payload, numbered
+    /// with `seq` if any.
+    pub(crate) fn new(checksum: Checksum, compressed: bool, seq: Option<u64>, payload: &[IoSlice<'_>]) -> FrameHeader {
+        FrameHeader::with_flags(checksum, if compressed { FRAME_COMPRESSED } else { 0 }, seq, payload)
+    }
+
+    /// Returns the header of a control frame with the given body.
+    fn control(checksum: Checksum, body: &[IoSlice<'_>]) -> FrameHeader {
+        FrameHeader::with_flags(checksum, FRAME_CONTROL, None, body)
+    }
+
+    fn with_flags(checksum: Checksum, flags: u32, seq: Option<u64>, payload: &[IoSlice<'_>]) -> FrameHeader {
+        let len: usize = payload.iter().map(|p| p.len()).sum();
+        let mut bytes = [0u8; MAX_FRAME_HEADER_SIZE];
+        bytes[..FRAME_LEN_SIZE].copy_from_slice(&(len as u32 | flags).to_le_bytes());
+        let mut end = FRAME_LEN_SIZE;
+        if let Some(seq) = seq {
+            bytes[end..end + FRAME_SEQ_SIZE].copy_from_slice(&seq.to_le_bytes());
+            end += FRAME_SEQ_SIZE;
+        }
+        if checksum == Checksum::Crc32c {
+            let crc = payload.iter().fold(crc32c::crc32c(&bytes[..end]), |crc, p| crc32c::crc32c_append(crc, p));
+            bytes[end..end + size_of::<u32>()].copy_from_slice(&crc.to_le_bytes());
+        }
+        FrameHeader { bytes, len: header_size(checksum, seq.is_some()) }
+    }
+
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+}
+
+/// Returns the length field of a frame header.
+fn frame_len(compressed: bool, len: usize) -> [u8; FRAME_LEN_SIZE] {
+    let flags = if compressed { FRAME_COMPRESSED } else { 0 };
+    (len as u32 | flags).to_le_bytes()
+}
+
+/// Returns whether the CRC32C ending a frame header matches the fields it
+/// follows and the body of the frame.
+fn crc_matches(header: &[u8], body: &[u8]) -> bool {
+    let (fields, crc) = header.split_at(header.len() - size_of::<u32>());
+    let mut crc_bs = [0u8; size_of::<u32>()];
+    crc_bs.copy_from_slice(crc);
+    crc32c::crc32c_append(crc32c::crc32c(fields), body) == u32::from_le_bytes(crc_bs)
+}
+
+/// Returns the size of the header of a frame, control frames never being
+/// numbered.
+fn header_size(checksum: Checksum, numbered: bool) -> usize {
+    let seq = if numbered { FRAME_SEQ_SIZE } else { 0 };
+    match checksum {
+        Checksum::None => FRAME_LEN_SIZE + seq,
+        Checksum::Crc32c => FRAME_LEN_SIZE + seq + size_of::<u32>(),
+    }
+}
+
+/// Returns the sequence number of the numbered frame `data` starts with, if
+/// its header was received.
+fn frame_seq(data: &[u8]) -> Option<u64> {
+    data.get(FRAME_LEN_SIZE..FRAME_LEN_SIZE + FRAME_SEQ_SIZE).map(le_u64)
+}
+
+/// Returns the nonce space of the control frames of substream `id`.
+fn control_space(id: usize) -> u32 {
+    DATA_SPACE + 1 + id as u32
+}
+
+/// Turns fragments into frames, compressing then encrypting their payload
+/// before computing their header.
+#[derive(Default)]
+pub(crate) struct Encoder {
+    checksum: Checksum,
+    deflater: Deflater,
+    sealer: Option<Sealer>,
+    /// Sequence number of the next frame, from which its nonce is derived,
+    /// and whether it is carried by the header.
+    seq: u64,
+    numbered: bool,
+}
+
+impl Encoder {
+    /// Returns the header and the payload of the next frame, which is only
+    /// numbered once `sent` is called, so that a frame for which there is no
+    /// room can be encoded again.
+    fn encode<'a>(&'a mut self, mut payload: Vec<IoSlice<'a>>) -> (FrameHeader, Vec<IoSlice<'a>>) {
+        let compressed = match self.deflater.deflate(&payload) {
+            Some(c) => {
+                payload = vec![IoSlice::new(c)];
+                true
+            }
+            None => false,
+        };
+        if let Some(sealer) = self.sealer.as_mut() {
+            let len = TAG_SIZE + payload.iter().map(|p| p.len()).sum::<usize>();
+            payload = vec![IoSlice::new(sealer.seal(DATA_SPACE, self.seq, &frame_len(compressed, len), &payload))];
+        }
+        (FrameHeader::new(self.checksum, compressed, self.numbered.then_some(self.seq), &payload), payload)
+    }
+
+    fn sent(&mut self) {
+        self.seq += 1;
+    }
+}
+
+/// A buffer of a substream, either holding data pulled from the socket in
+/// chunks as large as the free space allows, out of which frame headers are
+/// parsed and payloads handed out without further system calls, or frames
+/// that have been scheduled on the substream and not yet written to it.
+///
+/// The memory is allocated once and never moves, so that it can be handed
+/// to the kernel. While an operation is in flight on it the buffer is pinned,
+/// then data is neither compacted nor rewound, only appended or consumed.
+pub(crate) struct Buffer {
+    buf: Box<[u8]>,
+    start: usize,
+    end: usize,
+    pub(crate) pinned: bool,
+}
+
+impl Buffer {
+    pub(crate) fn new(capacity: usize) -> Buffer {
+        Buffer { buf: vec![0u8; capacity].into_boxed_slice(), start: 0, end: 0, pinned: false }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    pub(crate) fn is_full(&self) -> bool {
+        self.len() == self.buf.len()
+    }
+
+    pub(crate) fn data(&self) -> &[u8] {
+        &self.buf[self.start..self.end]
+    }
+
+    fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.buf[self.start..self.end]
+    }
+
+    pub(crate) fn consume(&mut self, n: usize) {
+        self.start += n;
+        if self.start == self.end && !self.pinned {
+            self.start = 0;
+            self.end = 0;
+        }
+    }
+
+    /// Returns the free space at the tail of the buffer, moving the buffered
+    /// data to the front when the tail is exhausted.
+    pub(crate) fn spare(&mut self) -> &mut [u8] {
+        if self.end == self.buf.len() && self.start > 0 && !self.pinned {
+            self.compact();
+        }
+        &mut self.buf[self.end..]
+    }
+
+    pub(crate) fn commit(&mut self, n: usize) {
+        self.end += n;
+    }
+
+    fn compact(&mut self) {
+        self.buf.copy_within(self.start..self.end, 0);
+        self.end -= self.start;
+        self.start = 0;
+    }
+
+    /// Returns whether `len` more bytes can be appended.
+    fn fits(&self, len: usize) -> bool {
+        self.buf.len() - self.end >= len || (!self.pinned && self.buf.len() - self.len() >= len)
+    }
+
+    /// Appends a frame made of the given header and payload, returns `false`
+    /// if there is no room left for it.
+    pub(crate) fn push_frame(&mut self, header: &FrameHeader, payload: &[IoSlice<'_>]) -> bool {
+        let header = header.as_bytes();
+        let len = header.len() + payload.iter().map(|p| p.len()).sum::<usize>();
+        if !self.fits(len) {
+            return false;
+        }
+        if self.buf.len() - self.end < len {
+            self.compact();
+        }
+        let mut end = self.end;
+        self.buf[end..end + header.len()].copy_from_slice(header);
+        end += header.len();
+        for p in payload.iter() {
+            self.buf[end..end + p.len()].copy_from_slice(p);
+            end += p.len();
+        }
+        self.end = end;
+        true
+    }
+
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    pub(crate) fn iovec(&mut self) -> libc::iovec {
+        libc::iovec { iov_base: self.buf.as_mut_ptr().cast(), iov_len: self.buf.len() }
+    }
+
+    /// Leaks the memory of the buffer, which the kernel may still access.
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    pub(crate) fn leak(&mut self) {
+        Box::leak(std::mem::take(&mut self.buf));
+        self.start = 0;
+        self.end = 0;
+    }
+}
+
+/// The bonding engine: the substreams, the poller driving them and the
+/// framing state of both directions.
+///
+/// Frames are scheduled round-robin, `tx_stream` and `rx_stream` being the
+/// substreams that carry the next frame to be sent and received, while
+/// `readable` is the number of payload bytes left in the frame being received.
+/// When frames are checked, a frame is only handed out once it has been
+/// received as a whole and its checksum verified. Likewise a compressed frame
+/// is received as a whole, then decompressed to `inflated` from which its
+/// payload is handed out, and an encrypted frame is received as a whole and
+/// decrypted in place. Frames are numbered in each direction across all the
+/// substreams, the nonce of an encrypted frame being derived from its number.
+///
+/// When frames are scheduled by latency, rather than round-robin, or sent on
+/// several substreams, their headers carry their number. Each substream
+/// still carries its frames in order, thus the next frame to be received is
+/// the first one of some substream: it is looked for at the head of all of
+/// them, the others waiting in their receive buffers for their turn, while
+/// the copies of the frames received already are dropped.
+///
+/// When heartbeats are settled with the peer, every wait sends them on the
+/// substreams that have been idle for the interval and pulls what all the
+/// substreams received, so that the silence of one of them is noticed while
+/// the others are heard from, whichever substream the bond waits on. Frames
+/// are then numbered, so that they can be scheduled on the other substreams
+/// while one of them is silent. Pings are sent and answered along with
+/// heartbeats, the round-trip time of each substream being measured from the
+/// moment its ping is scheduled to the moment the pong is parsed.
+pub(crate) struct Bond<T: Transport> {
+    pub(crate) substreams: Vec<Substream<T>>,
+    pub(crate) poller: Arc<polling::Poller>,
+    events: polling::Events,
+    edge: bool,
+    pub(crate) tx_stream: usize,
+    pub(crate) rx_stream: usize,
+    pub(crate) readable: usize,
+    checksum: Checksum,
+    encoder: Encoder,
+    /// Encrypts control frames, which may be sent while the encoder is taken.
+    control_sealer: Option<Sealer>,
+    inflater: Inflater,
+    inflated: Buffer,
+    opener: Option<Opener>,
+    /// Sequence number of the next frame to be received, whether frames carry
+    /// it, how they are scheduled and the number of substreams each one is
+    /// sent on unless a write asks for another.
+    rx_seq: u64,
+    pub(crate) numbered: bool,
+    scheduler: Scheduler,
+    redundancy: usize,
+    pub(crate) counters: IoCounters,
+    /// The time spent blocked in `wait`.
+    pub(crate) wait_time: Duration,
+    cid: Option<Uuid>,
+    pub(crate) span: Span,
+    observer: Option<Observer>,
+    stall_timeout: Option<Duration>,
+    /// The heartbeats settled with the peer, with the local miss threshold,
+    /// when the peer was last heard from on any substream as of the last
+    /// check, and when it resumed sending after having been silent on all
+    /// of them, from which the silence of the substreams is measured, and
+    /// since when the next frame can only have been sent on a substream
+    /// given up on.
+    heartbeat: Option<Heartbeat>,
+    heard: Instant,
+    resumed: Instant,
+    stranded_since: Option<Instant>,
+    /// The substream a frame is being written to straight from the caller's
+    /// buffers, on which no control frame may be scheduled meanwhile.
+    sending: Option<usize>,
+    /// Whether pings are answered, the interval they are sent at if any, and
+    /// the instant their timestamps count from.
+    pings: bool,
+    ping_interval: Option<Duration>,
+    epoch: Instant,
+    #[cfg(feature = "metrics")]
+    metrics: Option<Arc<BondMetrics>>,
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    uring: Option<Uring>,
+}
+
+impl<T: Transport> Bond<T> {
+    pub(crate) fn new(links: Vec<Link<T>>, config: &BondConfig, session: Session, cid: Option<Uuid>, span: Span) -> IoResult<Bond<T>> {
+        let Session { options, keys, heartbeat } = session;
+        let heartbeat = heartbeat.map(|interval| Heartbeat { interval, misses: config.heartbeat.unwrap_or_default().misses.max(MIN_MISSES) });
+        let poller = polling::Poller::new()?;
+        let edge = !config.oneshot && poller.supports_edge();
+        let mode = if edge { polling::PollMode::Edge } else { polling::PollMode::Oneshot };
+        let polled = config.backend == Backend::Poll;
+        if !polled && links.iter().any(Link::is_tls) {
+            return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "TLS substreams are only driven by the poll backend"));
+        }
+        // Frames are only copied to a transmission buffer when they are not
+        // written straight from the caller's buffers, control frames always are.
+        let tx_size = match (polled && config.io_mode == IoMode::Inline, heartbeat.is_some() || options.pings) {
+            (false, _) => TX_BUFFER_SIZE,
+            (true, true) => CONTROL_BUFFER_SIZE,
+            (true, false) => 0,
+        };
+        let now = Instant::now();
+        #[cfg(feature = "metrics")]
+        let metrics = config.metrics.as_ref().map(|m| m.bond_formed(cid, links.len()));
+        let mut substreams = Vec::with_capacity(links.len());
+        for (id, link) in links.into_iter().enumerate() {
+            if polled {
+                link.stream.set_nonblocking(true)?;
+                let interest = if edge { polling::Event::all(id) } else { polling::Event::none(id) };
+                // SAFETY: the stream is owned by the substream and deleted from the
+                // poller in `Drop`, before being closed.
+                unsafe { poller.add_with_mode(&link.stream.as_fd(), interest, mode)? };
+            }
+            let peer = events::peer_addr(&link.stream);
+            let substream_span = tracing::debug_span!(parent: &span, "substream", index = id, peer = field::Empty);
+            if let Some(peer) = peer {
+                substream_span.record("peer", field::display(peer));
+            }
+            substreams.push(Substream {
+                link,
+                readable: true,
+                writable: true,
+                eof: false,
+                rx: Buffer::new(RX_BUFFER_SIZE),
+                tx: Buffer::new(tx_size),
+                counters: SubstreamCounters::default(),
+                blocked_since: None,
+                stalled: false,
+                peer,
+                last_sent: now,
+                last_received: now,
+                silent: false,
+                lost: false,
+                last_ping: now,
+                pong: None,
+                rtt: RttEstimate::default(),
+                control_sent: 0,
+                control_received: 0,
+                written: 0,
+                read: 0,
+                acked: 0,
+                last_pong: None,
+                rate: None,
+                span: substream_span,
+                #[cfg(feature = "metrics")]
+                metrics: metrics.as_ref().map(|m| m.substreams[id].clone()),
+            });
+        }
+        #[cfg(all(target_os = "linux", feature = "io-uring"))]
+        let uring = match config.backend {
+            Backend::IoUring => Some(Uring::new(&mut substreams)?),
+            Backend::Poll => None,
+        };
+        let redundancy = config.redundancy.clamp(1, substreams.len());
+        let bond = Bond {
+            substreams,
+            poller: Arc::new(poller),
+            events: polling::Events::new(),
+            edge,
+            tx_stream: 0,
+            rx_stream: 0,
+            readable: 0,
+            checksum: options.checksum,
+            encoder: Encoder {
+                checksum: options.checksum,
+                deflater: Deflater::new(options.compression)?,
+                sealer: keys.as_ref().map(|k| Sealer::new(&k.tx)),
+                seq: 0,
+                numbered: options.numbered,
+            },
+            control_sealer: keys.as_ref().map(|k| Sealer::new(&k.tx)),
+            inflater: Inflater::new(options.compression)?,
+            inflated: Buffer::new(if options.compression == Compression::None { 0 } else { FRAGMENT_SIZE }),
+            opener: keys.as_ref().map(|k| Opener::new(&k.rx)),
+            rx_seq: 0,
+            numbered: options.numbered,
+            scheduler: config.scheduler,
+            redundancy,
+            counters: IoCounters::default(),
+            wait_time: Duration::ZERO,
+            cid,
+            span,
+            observer: config.observer.clone(),
+            stall_timeout: config.stall_timeout,
+            heartbeat,
+            heard: now,
+            resumed: now,
+            stranded_since: None,
+            sending: None,
+            pings: options.pings,
+            ping_interval: match config.scheduler {
+                Scheduler::RoundRobin => config.ping_interval,
+                Scheduler::Latency => Some(config.ping_interval.unwrap_or(DEFAULT_PING_INTERVAL)),
+            },
+            epoch: now,
+            #[cfg(feature = "metrics")]
+            metrics,
+            #[cfg(all(target_os = "linux", feature = "io-uring"))]
+            uring,
+        };
+        tracing::debug!(parent: &bond.span, substreams = bond.substreams.len(), options = ?options, edge, "Bond formed");
+        bond.notify(BondEventKind::BondFormed);
+        Ok(bond)
+    }
+
+    fn notify(&self, kind: BondEventKind) {
+        if let Some(observer) = self.observer.as_ref() {
+            observer.notify(kind, self.cid);
+        }
+    }
+
+    fn notify_substream(&self, kind: BondEventKind, id: usize) {
+        if let Some(observer) = self.observer.as_ref() {
+            observer.notify_peer(kind, self.cid, id, self.substreams[id].peer);
+        }
+    }
+
+    /// Reports the failure of substream `id`, which fails the bond, giving
+    /// the error the context of the substream unless it already is a
+    /// `BondError`.
+    fn fail(&self, id: usize, e: std::io::Error) -> std::io::Error {
+        let e = match BondError::of(&e) {
+            Some(_) => e,
+            None => BondError::SubstreamFailed { index: id, peer: self.substreams[id].peer, error: Arc::new(e) }.into(),
+        };
+        tracing::debug!(parent: &self.substreams[id].span, error = %e, "Substream failed");
+        #[cfg(feature = "metrics")]
+        if let Some(m) = self.metrics.as_ref() {
+            m.substream_failed();
+        }
+        self.notify_substream(BondEventKind::SubstreamFailed { error: e.to_string() }, id);
+        e
+    }
+
+    /// Reports the substreams that have not taken any frame for longer than
+    /// the stall timeout, once per stall.
+    fn check_stalls(&mut self) {
+        let Some(timeout) = self.stall_timeout else {
+            return;
+        };
+        for id in 0..self.substreams.len() {
+            let s = &mut self.substreams[id];
+            if !s.stalled && s.blocked_since.is_some_and(|t| t.elapsed() >= timeout) {
+                s.stalled = true;
+                tracing::debug!(parent: &s.span, ?timeout, "Substream stalled");
+                self.notify_substream(BondEventKind::SubstreamStalled, id);
+            }
+        }
+    }
+
+    /// Returns whether control frames are exchanged with the peer.
+    fn has_control(&self) -> bool {
+        self.heartbeat.is_some() || self.pings
+    }
+
+    /// Returns the timestamp of an instant, as carried by pings.
+    fn timestamp(&self, t: Instant) -> u64 {
+        t.duration_since(self.epoch).as_micros() as u64
+    }
+
+    /// Returns the stats of the substreams, but for their TCP state.
+    pub(crate) fn substream_stats(&self) -> Vec<SubstreamStats> {
+        self.substreams.iter().map(|s| SubstreamStats { counters: s.counters, tcp: None, rtt: s.rtt.get() }).collect()
+    }
+
+    /// Pulls what the substreams received and consumes the control frames
+    /// it starts with, then sends the control frames due. Returns whether
+    /// anything was received, and how long to wait at most for the next
+    /// ping or heartbeat to be sent in time.
+    fn keep_alive(&mut self) -> IoResult<(bool, Option<Duration>)> {
+        if !self.has_control() {
+            return Ok((false, None));
+        }
+        let mut received = false;
+        for id in 0..self.substreams.len() {
+            let s = &self.substreams[id];
+            if !s.eof && !s.rx.is_full() && s.readable {
+                received |= self.try_fill_rx(id)?.is_some();
+            }
+            // The current substream starts with the rest of the frame being
+            // received, if any.
+            if id != self.rx_stream || self.readable == 0 {
+                self.receive_control(id).map_err(|e| self.fail(id, e))?;
+            }
+        }
+        Ok((received, self.send_control()?))
+    }
+
+    /// Reports the substreams nothing was received on while another one was
+    /// heard from twice the heartbeat interval later, no frame being
+    /// scheduled on them until they are heard from again, and gives up on
+    /// those that stay silent for the miss threshold, as the peer is sending
+    /// heartbeats that do not make it through. Nothing is sent on them
+    /// anymore, for the peer to give up on them as well, and the bond fails
+    /// once none is left or the next frame can only have been sent on one of
+    /// them. The silence of a substream is measured against what was heard on
+    /// the others rather than against the time of the check, as a check may
+    /// come between the heartbeats the peer sends on each substream in turn.
+    /// Substreams are heard from when data is received on them or when they
+    /// are reported readable, and those whose receive buffer is full are not
+    /// read from, thus never held silent. Bonds of a single substream cannot
+    /// tell a silent peer from an idle one.
+    fn check_heartbeats(&mut self) -> IoResult<()> {
+        let Some(heartbeat) = self.heartbeat else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        // A peer that was not driving its bond resumes on one substream
+        // after the other, which does not make the last ones silent. While
+        // it is driven, heartbeats come in at most an interval apart, give
+        // or take the time it takes to be woken up.
+        let latest = self.substreams.iter().map(|s| s.last_received).max().unwrap_or(now);
+        if latest > self.heard {
+            let first = self.substreams.iter().map(|s| s.last_received).filter(|t| *t > self.heard).min().unwrap_or(latest);
+            if first.duration_since(self.heard) > 2 * heartbeat.interval {
+                self.resumed = first;
+            }
+            self.heard = latest;
+        }
+        // Heartbeats the peer sent together may be read an interval apart
+        // when this side was not woken up in time, so what was heard on the
+        // other substreams only counts once it is an interval old.
+        let horizon = now.checked_sub(heartbeat.interval).unwrap_or(now);
+        for id in 0..self.substreams.len() {
+            let s = &self.substreams[id];
+            let since = s.last_received.max(self.resumed);
+            // How long the peer went on sending on the other substreams.
+            let lead = self
+                .substreams
+                .iter()
+                .enumerate()
+                .filter(|(other, o)| *other != id && !o.eof)
+                .map(|(_, o)| o.last_received.min(horizon).saturating_duration_since(since))
+                .max()
+                .unwrap_or_default();
+            if s.eof || s.lost || s.rx.is_full() || lead < 2 * heartbeat.interval {
+                continue;
+            }
+            let silence = now.duration_since(since);
+            if !s.silent {
+                self.substreams[id].silent = true;
+                tracing::debug!(parent: &self.substreams[id].span, ?silence, "Substream went silent");
+                self.notify_substream(BondEventKind::SubstreamStalled, id);
+            }
+            if lead >= heartbeat.interval * heartbeat.misses {
+                self.substreams[id].lost = true;
+                let reason = format!("nothing received on substream {id} for {silence:?} while the others were heard from");
+                let e = self.fail(id, BondError::Timeout { substream: Some(id), reason }.into());
+                if self.substreams.iter().all(|s| s.eof || s.lost) {
+                    return Err(e);
+                }
+            }
+        }
+        let Some(id) = self.stranded() else {
+            self.stranded_since = None;
+            return Ok(());
+        };
+        // The peer may write the frames it scheduled on several substreams
+        // in any order, so the frame is only waited for until the others are
+        // heard from twice the interval later, unless the substream is heard
+        // from meanwhile.
+        let since = (*self.stranded_since.get_or_insert(now)).max(self.substreams[id].last_received).max(self.resumed);
+        let lead = self.substreams.iter().map(|s| s.last_received.min(horizon).saturating_duration_since(since)).max().unwrap_or_default();
+        if lead < 2 * heartbeat.interval {
+            return Ok(());
+        }
+        let reason = format!("frame {} can only have been sent on substream {id}, which went silent", self.rx_seq);
+        Err(BondError::Timeout { substream: Some(id), reason }.into())
+    }
+
+    /// Returns the substream given up on that the next numbered frame was
+    /// sent on, if every other one that may still deliver it starts with a
+    /// later frame while it has nothing left to be read.
+    fn stranded(&self) -> Option<usize> {
+        if !self.numbered || self.readable > 0 || !self.substreams.iter().any(|s| s.lost) {
+            return None;
+        }
+        let mut lost = None;
+        for (id, s) in self.substreams.iter().enumerate() {
+            let data = s.rx.data();
+            if s.lost && data.is_empty() && !s.readable {
+                lost = lost.or(Some(id));
+            } else if !(s.eof && data.is_empty()) {
+                // Control frames ahead are consumed before the next one is
+                // looked for, and carry no number.
+                let later = data.len() >= FRAME_LEN_SIZE && le_u32(&data[..FRAME_LEN_SIZE]) & FRAME_CONTROL == 0 && frame_seq(data).is_some_and(|seq| seq > self.rx_seq);
+                if !later {
+                    return None;
+                }
+            }
+        }
+        lost
+    }
+
+    /// Schedules the control frames due on every substream: the pong
+    /// answering the last ping received, a ping every ping interval, and a
+    /// heartbeat once nothing was handed to it for the heartbeat interval,
+    /// unless it is still busy with earlier frames. Returns the time until
+    /// the next ping or heartbeat is due, if any.
+    ///
+    /// Control frames are sent whenever the bond sends frames as well, so
+    /// that a peer hearing from one substream hears from the others.
+    pub(crate) fn send_control(&mut self) -> IoResult<Option<Duration>> {
+        if !self.has_control() {
+            return Ok(None);
+        }
+        let now = Instant::now();
+        let mut next = None;
+        for id in 0..self.substreams.len() {
+            // Nothing more is sent on the substreams given up on, for the peer
+            // to notice that they went silent.
+            if self.substreams[id].lost {
+                continue;
+            }
+            let busy = self.sending == Some(id);
+            if let Some(ts) = self.substreams[id].pong
+                && !busy
+                && self.push_control(id, CONTROL_PONG, &[ts.to_le_bytes(), self.substreams[id].read.to_le_bytes()].concat())
+            {
+                io_trace!(parent: &self.substreams[id].span, "Scheduled pong");
+                self.substreams[id].pong = None;
+                self.substreams[id].last_sent = now;
+            }
+            if let Some(interval) = self.ping_interval {
+                let since = now.duration_since(self.substreams[id].last_ping);
+                if since < interval {
+                    next = earliest(next, Some(interval - since));
+                } else {
+                    if !busy && self.push_control(id, CONTROL_PING, &self.timestamp(now).to_le_bytes()) {
+                        io_trace!(parent: &self.substreams[id].span, "Scheduled ping");
+                        self.substreams[id].last_ping = now;
+                        self.substreams[id].last_sent = now;
+                    }
+                    next = earliest(next, Some(interval));
+                }
+            }
+            if let Some(heartbeat) = self.heartbeat {
+                let idle = now.duration_since(self.substreams[id].last_sent);
+                if idle < heartbeat.interval {
+                    next = earliest(next, Some(heartbeat.interval - idle));
+                } else {
+                    if !busy && self.substreams[id].tx.is_empty() && self.push_control(id, CONTROL_HEARTBEAT, &[]) {
+                        io_trace!(parent: &self.substreams[id].span, "Scheduled heartbeat");
+                        self.substreams[id].last_sent = now;
+                    }
+                    next = earliest(next, Some(heartbeat.interval));
+                }
+            }
+            if !busy && !self.substreams[id].tx.is_empty() {
+                self.try_flush_tx(id)?;
+            }
+        }
+        Ok(next)
+    }
+
+    /// Schedules a control frame of the given kind, followed by `data`, on
+    /// substream `id`. Returns `false` if there is no room left for it.
+    fn push_control(&mut self, id: usize, kind: u8, data: &[u8]) -> bool {
+        let kind = [kind];
+        let mut body = vec![IoSlice::new(&kind), IoSlice::new(data)];
+        let s = &mut self.substreams[id];
+        if let Some(sealer) = self.control_sealer.as_mut() {
+            let len = TAG_SIZE + 1 + data.len();
+            body = vec![IoSlice::new(sealer.seal(control_space(id), s.control_sent, &(len as u32 | FRAME_CONTROL).to_le_bytes(), &body))];
+        }
+        let header = FrameHeader::control(self.checksum, &body);
+        if !s.tx.push_frame(&header, &body) {
+            return false;
+        }
+        s.control_sent += 1;
+        s.written += (header.as_bytes().len() + body.iter().map(|b| b.len()).sum::<usize>()) as u64;
+        true
+    }
+
+    /// Consumes the control frames the receive buffer of substream `id`
+    /// starts with, which must be on a frame boundary. Returns whether it
+    /// starts with a control frame that has not been fully received yet.
+    fn receive_control(&mut self, id: usize) -> IoResult<bool> {
+        let header_size = header_size(self.checksum, false);
+        let now = Instant::now();
+        let timestamp = self.timestamp(now);
+        loop {
+            let s = &mut self.substreams[id];
+            let data = s.rx.data();
+            if data.len() < FRAME_LEN_SIZE {
+                return Ok(false);
+            }
+            let mut len_bs = [0u8; FRAME_LEN_SIZE];
+            len_bs.copy_from_slice(&data[..FRAME_LEN_SIZE]);
+            if u32::from_le_bytes(len_bs) & FRAME_FLAGS != FRAME_CONTROL {
+                return Ok(false);
+            }
+            let len = (u32::from_le_bytes(len_bs) & !FRAME_FLAGS) as usize;
+            if len == 0 || len > MAX_CONTROL_SIZE {
+                return Err(BondError::protocol(
+                    Some(id),
+                    format!("Control frame at offset {} of substream {id} has an invalid length: {len} bytes", s.counters.bytes_received),
+                ));
+            }
+            if data.len() < header_size + len {
+                return Ok(true);
+            }
+            if self.checksum == Checksum::Crc32c && !crc_matches(&data[..header_size], &data[header_size..header_size + len]) {
+                return Err(BondError::protocol(Some(id), format!("Checksum mismatch on a control frame at offset {} of substream {id}", s.counters.bytes_received)));
+            }
+            // The body of encrypted control frames follows their tag.
+            let mut start = header_size;
+            if let Some(opener) = self.opener.as_ref() {
+                if len <= TAG_SIZE || !opener.open(control_space(id), s.control_received, &len_bs, &mut s.rx.data_mut()[header_size..header_size + len]) {
+                    return Err(BondError::AuthenticationFailed {
+                        substream: Some(id),
+                        reason: format!("Control frame at offset {} of substream {id} failed authentication", s.counters.bytes_received),
+                    }
+                    .into());
+                }
+                start += TAG_SIZE;
+            }
+            s.control_received += 1;
+            let body = &s.rx.data()[start..header_size + len];
+            match body[0] {
+                CONTROL_HEARTBEAT => {
+                    io_trace!(parent: &s.span, "Heartbeat");
+                }
+                CONTROL_PING if self.pings && body.len() == PING_SIZE => {
+                    let ts = le_u64(&body[1..PING_SIZE]);
+                    io_trace!(parent: &s.span, ts, "Ping");
+                    s.pong = Some(ts);
+                }
+                CONTROL_PONG if self.pings && body.len() == PONG_SIZE => {
+                    let Some(rtt) = timestamp.checked_sub(le_u64(&body[1..PING_SIZE])) else {
+                        return Err(BondError::protocol(Some(id), format!("Pong on substream {id} echoes a ping that was never sent")));
+                    };
+                    let rtt = Duration::from_micros(rtt);
+                    io_trace!(parent: &s.span, ?rtt, "Pong");
+                    s.rtt.update(rtt);
+                    s.acknowledged(now, le_u64(&body[PING_SIZE..PONG_SIZE]));
+                }
+                kind => {
+                    return Err(BondError::protocol(Some(id), format!("Unknown control frame {kind:#04x} of {len} bytes on substream {id}")));
+                }
+            }
+            s.rx.consume(header_size + len);
+        }
+    }
+
+    /// Blocks until the poller reports new events, or it is notified, and
+    /// records the readiness they carry on the respective substreams. With
+    /// heartbeats, it returns right away when the substreams had received
+    /// data that was pulled beforehand.
+    ///
+    /// When edge-triggered mode is not supported, or oneshot mode was asked
+    /// for, every substream still waiting for readiness is re-armed before
+    /// blocking.
+    pub(crate) fn wait(&mut self, timeout: Option<Duration>) -> IoResult<()> {
+        let (received, heartbeat) = self.keep_alive()?;
+        if received {
+            return Ok(());
+        }
+        #[cfg(all(target_os = "linux", feature = "io-uring"))]
+        if let Some(uring) = self.uring.as_mut() {
+            let start = Instant::now();
+            let res = uring.wait(&mut self.substreams, &self.poller, &mut self.counters, earliest(timeout, heartbeat));
+            self.wait_time += start.elapsed();
+            #[cfg(feature = "metrics")]
+            self.record_reorder_depth();
+            if let Some((id, e)) = res? {
+                return Err(self.fail(id, e));
+            }
+            return self.check_heartbeats();
+        }
+        if !self.edge {
+            for (id, s) in self.substreams.iter().enumerate() {
+                let event = polling::Event::new(id, !s.readable, !s.writable);
+                self.poller.modify_with_mode(s.link.stream.as_fd(), event, polling::PollMode::Oneshot)?;
+                self.counters.rearms += 1;
+                self.counters.syscalls += 1;
+            }
+        }
+        self.events.clear();
+        io_trace!("Polling for substreams readiness");
+        let start = Instant::now();
+        // Waiting no longer than the stall timeout lets stalls be noticed,
+        // and no longer than the next heartbeat lets it be sent in time.
+        let res = self.poller.wait(&mut self.events, earliest(timeout.or(self.stall_timeout), heartbeat));
+        self.wait_time += start.elapsed();
+        res?;
+        self.check_stalls();
+        self.counters.polls += 1;
+        self.counters.syscalls += 1;
+        for e in self.events.iter() {
+            io_trace!(substream = e.key, readable = e.readable, writable = e.writable, "Substream ready");
+            if let Some(s) = self.substreams.get_mut(e.key) {
+                s.readable |= e.readable;
+                s.writable |= e.writable;
+                if e.readable && self.heartbeat.is_some() {
+                    s.heard();
+                }
+            }
+        }
+        self.check_heartbeats()?;
+        #[cfg(feature = "metrics")]
+        self.record_reorder_depth();
+        Ok(())
+    }
+
+    /// Reports the bytes received on the substreams other than the one
+    /// carrying the next frame, sampled whenever the bond waits for them.
+    #[cfg(feature = "metrics")]
+    fn record_reorder_depth(&self) {
+        if let Some(m) = self.metrics.as_ref() {
+            let depth: usize = self.substreams.iter().enumerate().filter(|(id, _)| *id != self.rx_stream).map(|(_, s)| s.rx.len()).sum();
+            m.reorder_depth.store(depth as u64, std::sync::atomic::Ordering::Relaxed);
+        }
+    }
+
+    /// Writes all the given buffers on the current transmission substream,
+    /// gathering them in as few `write_vectored` calls as possible.
+    ///
+    /// Returns `Ok(None)` if the substream was given up on for being silent
+    /// before it took any of them, and fails the bond if it was given up on
+    /// after taking part of them.
+    fn write_loop(&mut self, mut bufs: &mut [IoSlice<'_>]) -> IoResult<Option<usize>> {
+        let len: usize = bufs.iter().map(|b| b.len()).sum();
+        let id = self.tx_stream;
+        self.sending = Some(id);
+        // A heartbeat scheduled on the substream goes out before the frame.
+        while !self.substreams[id].tx.is_empty() {
+            self.try_flush_tx(id)?;
+            if !self.substreams[id].tx.is_empty() {
+                self.wait(None)?;
+                if self.substreams[id].lost {
+                    self.sending = None;
+                    return Ok(None);
+                }
+            }
+        }
+        let mut index = 0;
+        while index < len {
+            if !self.substreams[id].writable {
+                self.wait(None)?;
+                if self.substreams[id].lost {
+                    if index == 0 {
+                        self.sending = None;
+                        return Ok(None);
+                    }
+                    let reason = format!("substream {id} went silent after taking {index} bytes of a frame");
+                    return Err(self.fail(id, BondError::Timeout { substream: Some(id), reason }.into()));
+                }
+                continue;
+            }
+            self.counters.writes += 1;
+            self.counters.syscalls += 1;
+            match self.substreams[id].link.write_vectored(bufs) {
+                Ok(0) => {
+                    self.sending = None;
+                    return Ok(Some(0));
+                }
+                Ok(n) => {
+                    io_trace!(parent: &self.substreams[id].span, written = n, "Wrote frame bytes");
+                    self.substreams[id].unblocked();
+                    index += n;
+                    IoSlice::advance_slices(&mut bufs, n);
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    self.substreams[id].blocked();
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(self.fail(id, e)),
+            }
+        }
+        self.sending = None;
+        Ok(Some(len))
+    }
+
+    /// Sends the given buffers as a sequence of frames, each one on `copies`
+    /// substreams or as many as the bond sends them on if `None`, blocking
+    /// until all of them have been written.
+    pub(crate) fn write_frames(&mut self, bufs: &[IoSlice<'_>], copies: Option<usize>) -> IoResult<usize> {
+        #[cfg(all(target_os = "linux", feature = "io-uring"))]
+        if self.uring.is_some() {
+            return self.write_buffered(bufs, copies);
+        }
+        // The compressed or encrypted fragments are borrowed from the encoder
+        // while writing them.
+        let mut encoder = std::mem::take(&mut self.encoder);
+        let res = self.write_direct(bufs, &mut encoder, self.copies(copies));
+        self.encoder = encoder;
+        let len = res?;
+        self.flush_records()?;
+        self.send_control()?;
+        Ok(len)
+    }
+
+    /// Writes the TLS records left pending on every substream but those given
+    /// up on, blocking until all of them have been written.
+    fn flush_records(&mut self) -> IoResult<()> {
+        for id in 0..self.substreams.len() {
+            while !self.substreams[id].lost && self.substreams[id].link.has_records() {
+                if !self.substreams[id].writable {
+                    self.wait(None)?;
+                    continue;
+                }
+                self.counters.writes += 1;
+                self.counters.syscalls += 1;
+                match self.substreams[id].link.flush_records() {
+                    Ok(true) => self.substreams[id].unblocked(),
+                    Ok(false) => self.substreams[id].blocked(),
+                    Err(e) => return Err(self.fail(id, e)),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the number of substreams a frame is sent on when a write asks
+    /// for `copies` of it, or the bond's own number if `None`.
+    fn copies(&self, copies: Option<usize>) -> usize {
+        copies.map_or(self.redundancy, |n| n.clamp(1, self.substreams.len()))
+    }
+
+    fn write_direct(&mut self, bufs: &[IoSlice<'_>], encoder: &mut Encoder, copies: usize) -> IoResult<usize> {
+        let len: usize = bufs.iter().map(|b| b.len()).sum();
+        io_trace!(len, copies, "Writing payload");
+        let mut index = 0;
+        while index < len {
+            // Each fragment is sent along with its header in a single vectored write.
+            let flen = std::cmp::min(FRAGMENT_SIZE, len - index);
+            let (header, payload) = encoder.encode(io_slices(bufs, index, flen));
+            let mut frame = Vec::with_capacity(payload.len() + 1);
+            frame.push(IoSlice::new(header.as_bytes()));
+            frame.extend(payload);
+            let wire = frame.iter().map(|f| f.len()).sum();
+            // Copies are written one after the other, thus only to the
+            // substreams whose socket took everything written so far.
+            let mut ids = destinations(&self.substreams, self.scheduler, self.tx_stream, wire, copies, |s| copies == 1 || s.writable);
+            if ids.is_empty() {
+                // Blocks on the first substream that may take it.
+                ids = destinations(&self.substreams, self.scheduler, self.tx_stream, wire, 1, |_| true);
+                ids.resize(1, self.tx_stream);
+            }
+            let mut sent = false;
+            let mut next = 0;
+            while let Some(&id) = ids.get(next) {
+                next += 1;
+                self.tx_stream = id;
+                io_trace!(parent: &self.substreams[id].span, len = flen, "Sending fragment");
+                let Some(wire) = self.write_loop(&mut frame.clone())? else {
+                    // The substream was given up on before taking any of the
+                    // frame, which goes to another one unless a copy is.
+                    if !sent && next == ids.len() {
+                        ids.extend(destinations(&self.substreams, self.scheduler, id, wire, 1, |_| true));
+                    }
+                    continue;
+                };
+                if wire == 0 {
+                    return Ok(0);
+                }
+                self.counters.wire_bytes += wire as u64;
+                self.substreams[id].sent(wire as u64);
+                sent = true;
+            }
+            encoder.sent();
+            self.counters.payload_bytes += flen as u64;
+            index += flen;
+            self.tx_stream = (self.tx_stream + 1) % self.substreams.len();
+        }
+        Ok(len)
+    }
+
+    /// Copies a frame carrying the given payload to the transmission buffers
+    /// of the substreams it is scheduled on, `copies` of them or as many as
+    /// the bond sends frames on if `None`, moving on to the next one. Returns
+    /// `false` if none of them has room left for it.
+    pub(crate) fn push_frame(&mut self, payload: &[IoSlice<'_>], copies: Option<usize>) -> bool {
+        let copies = self.copies(copies);
+        let flen: usize = payload.iter().map(|p| p.len()).sum();
+        let (header, payload) = self.encoder.encode(payload.to_vec());
+        let wire = header.as_bytes().len() + payload.iter().map(|p| p.len()).sum::<usize>();
+        let ids = destinations(&self.substreams, self.scheduler, self.tx_stream, wire, copies, |s| s.tx.fits(wire));
+        let Some(&last) = ids.last() else {
+            return false;
+        };
+        let wire = wire as u64;
+        for id in ids {
+            let s = &mut self.substreams[id];
+            s.tx.push_frame(&header, &payload);
+            io_trace!(parent: &s.span, len = flen, "Scheduled fragment");
+            s.sent(wire);
+            self.counters.wire_bytes += wire;
+        }
+        self.counters.payload_bytes += flen as u64;
+        self.encoder.sent();
+        self.tx_stream = (last + 1) % self.substreams.len();
+        true
+    }
+
+    /// Sends the given buffers as a sequence of frames copied to the
+    /// transmission buffers, so that the writes on all the substreams can be
+    /// submitted at once, blocking until all of them have been written.
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    fn write_buffered(&mut self, bufs: &[IoSlice<'_>], copies: Option<usize>) -> IoResult<usize> {
+        let len: usize = bufs.iter().map(|b| b.len()).sum();
+        io_trace!(len, "Writing payload");
+        let mut index = 0;
+        while index < len {
+            let flen = std::cmp::min(FRAGMENT_SIZE, len - index);
+            if self.push_frame(&io_slices(bufs, index, flen), copies) {
+                index += flen;
+            } else {
+                self.flush_tx()?;
+                self.wait(None)?;
+            }
+        }
+        while !self.flush_tx()? {
+            self.wait(None)?;
+        }
+        self.send_control()?;
+        Ok(len)
+    }
+
+    /// Starts writing the frames scheduled on every substream, returns
+    /// whether all of them have been written.
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    fn flush_tx(&mut self) -> IoResult<bool> {
+        for id in 0..self.substreams.len() {
+            self.try_flush_tx(id)?;
+        }
+        Ok(self.substreams.iter().all(|s| s.tx.is_empty()))
+    }
+
+    /// Writes the frames scheduled on substream `id` without blocking,
+    /// returns whether anything was written.
+    pub(crate) fn try_flush_tx(&mut self, id: usize) -> IoResult<bool> {
+        #[cfg(all(target_os = "linux", feature = "io-uring"))]
+        if let Some(uring) = self.uring.as_mut() {
+            uring.submit_tx(id, &mut self.substreams[id], &mut self.counters)?;
+            return Ok(false);
+        }
+        let s = &mut self.substreams[id];
+        let mut progress = false;
+        while s.writable && !s.is_flushed() {
+            self.counters.writes += 1;
+            self.counters.syscalls += 1;
+            // Once the frames are written, what is left are TLS records.
+            let res = if s.tx.is_empty() { s.link.flush().map(|()| None) } else { s.link.write(s.tx.data()).map(Some) };
+            match res {
+                Ok(None) => progress = true,
+                Ok(Some(0)) => return Err(self.fail(id, std::io::ErrorKind::WriteZero.into())),
+                Ok(Some(n)) => {
+                    io_trace!(parent: &s.span, written = n, "Flushed scheduled frames");
+                    s.tx.consume(n);
+                    s.unblocked();
+                    progress = true;
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    s.blocked();
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(self.fail(id, e)),
+            }
+        }
+        Ok(progress)
+    }
+
+    /// Pulls more data from the socket of substream `id` into its receive
+    /// buffer without blocking.
+    ///
+    /// Returns `Ok(None)` if the socket has no data available, and
+    /// `Ok(Some(0))` if the substream was closed.
+    pub(crate) fn try_fill_rx(&mut self, id: usize) -> IoResult<Option<usize>> {
+        #[cfg(all(target_os = "linux", feature = "io-uring"))]
+        if let Some(uring) = self.uring.as_mut() {
+            uring.submit_rx(id, &mut self.substreams[id], &mut self.counters)?;
+            return Ok(None);
+        }
+        let s = &mut self.substreams[id];
+        debug_assert!(!s.rx.is_full());
+        while s.readable {
+            self.counters.reads += 1;
+            self.counters.syscalls += 1;
+            match s.link.read(s.rx.spare()) {
+                Ok(0) => {
+                    s.eof = true;
+                    return Ok(Some(0));
+                }
+                Ok(n) => {
+                    io_trace!(parent: &s.span, read = n, "Received frame bytes");
+                    s.filled(n);
+                    return Ok(Some(n));
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    s.readable = false;
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(self.fail(id, e)),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Pulls more data from the socket of substream `id` into its receive
+    /// buffer, returns `false` if the substream was closed.
+    fn fill_rx(&mut self, id: usize) -> IoResult<bool> {
+        let len = self.substreams[id].rx.len();
+        loop {
+            match self.try_fill_rx(id)? {
+                Some(n) => return Ok(n > 0),
+                // Completions are applied to the buffers while waiting.
+                None if self.substreams[id].rx.len() > len => return Ok(true),
+                None if self.substreams[id].eof => return Ok(false),
+                None => self.wait(None)?,
+            }
+        }
+    }
+
+    /// Parses the frame headers available in the receive buffer of the
+    /// current substream, returns the number of payload bytes of the current
+    /// frame that can be consumed without reading from the socket.
+    ///
+    /// Fails with `InvalidData` if a frame does not match its checksum, fails
+    /// authentication or cannot be decompressed.
+    pub(crate) fn ready_payload(&mut self) -> IoResult<usize> {
+        self.parse_frames().map_err(|e| self.fail(self.rx_stream, e))
+    }
+
+    fn parse_frames(&mut self) -> IoResult<usize> {
+        let header_size = header_size(self.checksum, self.numbered);
+        while self.readable == 0 {
+            if self.numbered && !self.locate()? {
+                return Ok(0);
+            }
+            let id = self.rx_stream;
+            if self.receive_control(id)? {
+                return Ok(0);
+            }
+            let s = &mut self.substreams[id];
+            let data = s.rx.data();
+            if data.len() < header_size {
+                return Ok(0);
+            }
+            let mut len_bs = [0u8; FRAME_LEN_SIZE];
+            len_bs.copy_from_slice(&data[..FRAME_LEN_SIZE]);
+            let compressed = u32::from_le_bytes(len_bs) & FRAME_COMPRESSED != 0;
+            let len = (u32::from_le_bytes(len_bs) & !FRAME_FLAGS) as usize;
+            io_trace!(parent: &s.span, len, compressed, "Frame header");
+            if self.checksum == Checksum::Crc32c || compressed || self.opener.is_some() {
+                if header_size + len > RX_BUFFER_SIZE {
+                    return Err(BondError::protocol(
+                        Some(id),
+                        format!("Frame {} at offset {} of substream {id} is too large: {len} bytes", s.counters.frames_received, s.counters.bytes_received),
+                    ));
+                }
+                if data.len() < header_size + len {
+                    return Ok(0);
+                }
+            }
+            if self.checksum == Checksum::Crc32c && !crc_matches(&data[..header_size], &data[header_size..header_size + len]) {
+                return Err(BondError::protocol(
+                    Some(id),
+                    format!("Checksum mismatch on frame {} at offset {} of substream {id}", s.counters.frames_received, s.counters.bytes_received),
+                ));
+            }
+            // The payload of encrypted frames follows their tag.
+            let mut body = header_size;
+            if let Some(opener) = self.opener.as_ref() {
+                if len < TAG_SIZE || !opener.open(DATA_SPACE, self.rx_seq, &len_bs, &mut s.rx.data_mut()[header_size..header_size + len]) {
+                    return Err(BondError::AuthenticationFailed {
+                        substream: Some(id),
+                        reason: format!("Frame {} at offset {} of substream {id} failed authentication", s.counters.frames_received, s.counters.bytes_received),
+                    }
+                    .into());
+                }
+                body += TAG_SIZE;
+            }
+            let mut plen = header_size + len - body;
+            if compressed {
+                plen = self.inflater.inflate(&s.rx.data()[body..header_size + len], self.inflated.spare()).map_err(|e| {
+                    BondError::protocol(
+                        Some(id),
+                        format!("Cannot decompress frame {} at offset {} of substream {id}: {e}", s.counters.frames_received, s.counters.bytes_received),
+                    )
+                })?;
+                self.inflated.commit(plen);
+                s.rx.consume(header_size + len);
+            } else {
+                s.rx.consume(body);
+            }
+            self.rx_seq += 1;
+            s.received((header_size + len) as u64);
+            self.counters.payload_bytes += plen as u64;
+            self.counters.wire_bytes += (header_size + len) as u64;
+            if plen == 0 {
+                self.rx_stream = (self.rx_stream + 1) % self.substreams.len();
+            } else {
+                self.readable = plen;
+            }
+        }
+        Ok(std::cmp::min(self.payload().len(), self.readable))
+    }
+
+    /// Makes the substream starting with the next numbered frame to be
+    /// received the current one, consuming the control frames ahead of it
+    /// and the copies of the frames already received on other substreams.
+    /// Returns `false` if none of them starts with it yet. Every substream
+    /// looked at is made the current one, so that its failures are reported
+    /// as its own.
+    fn locate(&mut self) -> IoResult<bool> {
+        let start = self.rx_stream;
+        for i in 0..self.substreams.len() {
+            let id = (start + i) % self.substreams.len();
+            self.rx_stream = id;
+            loop {
+                if self.receive_control(id)? {
+                    break;
+                }
+                match frame_seq(self.substreams[id].rx.data()) {
+                    Some(seq) if seq == self.rx_seq => return Ok(true),
+                    Some(seq) if seq < self.rx_seq && self.discard(id)? => {}
+                    _ => break,
+                }
+            }
+        }
+        self.rx_stream = start;
+        Ok(false)
+    }
+
+    /// Drops the copy of a frame already received the receive buffer of
+    /// substream `id` starts with. Returns `false` if it has not been fully
+    /// received yet.
+    fn discard(&mut self, id: usize) -> IoResult<bool> {
+        let header_size = header_size(self.checksum, true);
+        let s = &mut self.substreams[id];
+        let data = s.rx.data();
+        if data.len() < header_size {
+            return Ok(false);
+        }
+        let len = (le_u32(&data[..FRAME_LEN_SIZE]) & !FRAME_FLAGS) as usize;
+        if header_size + len > RX_BUFFER_SIZE {
+            return Err(BondError::protocol(
+                Some(id),
+                format!("Frame {} at offset {} of substream {id} is too large: {len} bytes", s.counters.frames_received, s.counters.bytes_received),
+            ));
+        }
+        if data.len() < header_size + len {
+            return Ok(false);
+        }
+        io_trace!(parent: &s.span, seq = frame_seq(data), len, "Discarded a copy");
+        s.rx.consume(header_size + len);
+        s.received((header_size + len) as u64);
+        s.counters.duplicates_received += 1;
+        self.counters.wire_bytes += (header_size + len) as u64;
+        Ok(true)
+    }
+
+    /// Pulls more data from the substream carrying the current frame, or from
+    /// all of them when the next numbered frame may come on any, waiting for
+    /// one of them if none has any. Returns `false` once no more frames can
+    /// be received.
+    fn fill_next(&mut self) -> IoResult<bool> {
+        if !self.numbered || self.readable > 0 {
+            return self.fill_rx(self.rx_stream);
+        }
+        let mut open = false;
+        for id in 0..self.substreams.len() {
+            let s = &self.substreams[id];
+            if s.eof || s.rx.is_full() {
+                continue;
+            }
+            open = true;
+            if self.try_fill_rx(id)?.is_some_and(|n| n > 0) {
+                return Ok(true);
+            }
+        }
+        if !open {
+            if self.substreams.iter().all(|s| s.eof) {
+                return Ok(false);
+            }
+            // Each substream carries its frames in order, the next one would
+            // be at the head of a full buffer.
+            return Err(self.fail(self.rx_stream, BondError::protocol(None, format!("Frame {} is missing while the receive buffers are full", self.rx_seq))));
+        }
+        // Completions are applied to the buffers while waiting, and whatever
+        // was received is parsed again.
+        self.wait(None)?;
+        Ok(true)
+    }
+
+    /// Returns whether no more frames can be received: the substream carrying
+    /// the next one is closed, or all of them when it is numbered.
+    pub(crate) fn closed(&self) -> bool {
+        if self.numbered && self.readable == 0 {
+            self.substreams.iter().all(|s| s.eof)
+        } else {
+            self.substreams[self.rx_stream].eof
+        }
+    }
+
+    /// Returns the bytes buffered for the current frame, which may extend
+    /// past its end.
+    pub(crate) fn payload(&self) -> &[u8] {
+        if self.inflated.is_empty() {
+            self.substreams[self.rx_stream].rx.data()
+        } else {
+            self.inflated.data()
+        }
+    }
+
+    /// Returns the buffered payload of the current frame, reading from the
+    /// substream only when nothing is buffered yet. An empty slice signals
+    /// that the bond was closed.
+    pub(crate) fn fill_buf(&mut self) -> IoResult<&[u8]> {
+        loop {
+            let n = self.ready_payload()?;
+            if n > 0 {
+                return Ok(&self.payload()[..n]);
+            }
+            if !self.fill_next()? {
+                return Ok(&[]);
+            }
+        }
+    }
+
+    pub(crate) fn consume(&mut self, amt: usize) {
+        let amt = std::cmp::min(amt, self.readable);
+        if amt == 0 {
+            return;
+        }
+        if self.inflated.is_empty() {
+            self.substreams[self.rx_stream].rx.consume(amt);
+        } else {
+            self.inflated.consume(amt);
+        }
+        self.readable -= amt;
+        if self.readable == 0 {
+            self.rx_stream = (self.rx_stream + 1) % self.substreams.len();
+            io_trace!(substream = self.rx_stream, "Frame consumed");
+        }
+    }
+
+    /// Fills all the given buffers with the payload of the incoming frames,
+    /// returns fewer bytes only if the bond was closed before.
+    pub(crate) fn read_frames(&mut self, bufs: &mut [IoSliceMut<'_>]) -> IoResult<usize> {
+        let len: usize = bufs.iter().map(|b| b.len()).sum();
+        io_trace!(len, substream = self.rx_stream, "Reading payload");
+        let mut bufs = bufs;
+        IoSliceMut::advance_slices(&mut bufs, 0);
+        let mut n = 0;
+        while n < len {
+            let data = self.fill_buf()?;
+            if data.is_empty() {
+                return Ok(n);
+            }
+            let copied = copy_to_slices(data, &mut bufs);
+            self.consume(copied);
+            n += copied;
+        }
+        Ok(len)
+    }
+}
+
+impl<T: Transport> Drop for Bond<T> {
+    fn drop(&mut self) {
+        #[cfg(all(target_os = "linux", feature = "io-uring"))]
+        if let Some(uring) = self.uring.as_mut()
+            && !uring.cancel(&mut self.substreams)
+        {
+            // Freeing buffers the kernel may still write to would corrupt
+            // whatever reuses the memory.
+            for s in self.substreams.iter_mut() {
+                s.rx.leak();
+                s.tx.leak();
+            }
+        }
+        for s in self.substreams.iter_mut() {
+            s.link.close();
+            let _ = self.poller.delete(s.link.stream.as_fd());
+        }
+        tracing::debug!(parent: &self.span, "Bond closed");
+        self.notify(BondEventKind::BondClosed);
+    }
+}
+
+/// Returns the substreams a frame of `len` bytes is handed to, `copies` of
+/// them at most. Frames sent once round-robin go to the `current` substream
+/// if `fits` accepts it, others to those `fits` accepts in the order the
+/// frame is expected to reach the peer on them, once every substream has
+/// answered a ping, or else in turn from the current one. Silent substreams
+/// are skipped, unless all of them are silent, and those given up on are
+/// never chosen.
+fn destinations<T>(substreams: &[Substream<T>], scheduler: Scheduler, current: usize, len: usize, copies: usize, fits: impl Fn(&Substream<T>) -> bool) -> Vec<usize> {
+    let n = substreams.len();
+    let mut ids: Vec<usize> = (0..n).map(|i| (current + i) % n).filter(|id| !substreams[*id].lost).collect();
+    if ids.iter().any(|id| !substreams[*id].silent) {
+        ids.retain(|id| !substreams[*id].silent);
+    }
+    if scheduler == Scheduler::RoundRobin && copies == 1 {
+        ids.truncate(1);
+    } else {
+        let now = Instant::now();
+        if let Some(arrivals) = substreams.iter().map(|s| s.arrival(now, len)).collect::<Option<Vec<_>>>() {
+            ids.sort_by_key(|id| arrivals[*id]);
+        }
+    }
+    ids.retain(|id| fits(&substreams[*id]));
+    ids.truncate(copies);
+    ids
+}
+
+/// Returns the shorter of two optional timeouts.
+fn earliest(a: Option<Duration>, b: Option<Duration>) -> Option<Duration> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        _ => a.or(b),
+    }
+}
+
+/// Reads a little-endian `u64` out of eight bytes.
+fn le_u64(bytes: &[u8]) -> u64 {
+    let mut b = [0u8; size_of::<u64>()];
+    b.copy_from_slice(bytes);
+    u64::from_le_bytes(b)
+}
+
+/// Reads a little-endian `u32` out of four bytes.
+fn le_u32(bytes: &[u8]) -> u32 {
+    let mut b = [0u8; size_of::<u32>()];
+    b.copy_from_slice(bytes);
+    u32::from_le_bytes(b)
+}
+
+/// Returns the slices covering `len` bytes of `bufs` starting at `offset`.
+pub(crate) fn io_slices<'a>(bufs: &'a [IoSlice<'_>], mut offset: usize, mut len: usize) -> Vec<IoSlice<'a>> {
+    let mut slices = Vec::with_capacity(bufs.len());
+    for b in bufs.iter() {
+        if len == 0 {
+            break;
+        }
+        if offset >= b.len() {
+            offset -= b.len();
+            continue;
+        }
+        let end = std::cmp::min(b.len(), offset + len);
+        slices.push(IoSlice::new(&b[offset..end]));
+        len -= end - offset;
+        offset = 0;
+    }
+    slices
+}
+
+/// Copies as much of `data` as fits in `bufs`, advancing them past the
+/// copied bytes, and returns the number of bytes copied.
+pub(crate) fn copy_to_slices(data: &[u8], bufs: &mut &mut [IoSliceMut<'_>]) -> usize {
+    let mut copied = 0;
+    while copied < data.len() && !bufs.is_empty() {
+        let k = std::cmp::min(bufs[0].len(), data.len() - copied);
+        bufs[0][..k].copy_from_slice(&data[copied..copied + k]);
+        copied += k;
+        IoSliceMut::advance_slices(bufs, k);
+    }
+    copied
+}
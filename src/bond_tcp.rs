@@ -1,10 +1,27 @@
 use std::collections::HashMap;
-use std::io::{Read, Result as IoResult, Write};
+use std::io::{IoSlice, IoSliceMut, Read, Result as IoResult, Write};
 use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::os::fd::OwnedFd;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use std::time::Duration;
 
+use socket2::SockRef;
+use tracing::{field, Span};
+
 use uuid::Uuid;
 
+use crate::background::Background;
+use crate::bond::{Bond, IoCounters};
+use crate::config::{BondConfig, IoMode, SocketOptions};
+use crate::error::BondError;
+use crate::events::{self, BondEventKind};
+use crate::handshake::{self, Session};
+use crate::link::Link;
+use crate::sockopt;
+use crate::stats::{self, BondStats};
+use crate::trace;
+use crate::transport::Transport;
+
 /// A TCP listener that bonds multiple connections from the same source address.
 ///
 /// `BondTcpListener` provides a transparent way to aggregate multiple TCP/IP connections
@@ -64,25 +81,160 @@ use uuid::Uuid;
 /// server address to complete the bonding process. The listener will
 /// block on `accept()` until all required connections are established.
 pub struct BondTcpListener {
-    listener: TcpListener,
-    stream_num: u8,
-    accepted_connections: HashMap<uuid::Uuid, std::vec::Vec<TcpStream>>,
+    listeners: Vec<TcpListener>,
+    /// Reports the listeners with pending connections, when there are
+    /// several of them.
+    poller: Option<polling::Poller>,
+    joins: Joins<TcpStream>,
+    /// The `IP_TTL` given to the accepted connections, 0 if unset.
+    ttl: AtomicU32,
+    /// Whether `accept` fails with `WouldBlock` rather than waiting when
+    /// there are several listeners, a single one being put in nonblocking
+    /// mode itself.
+    nonblocking: AtomicBool,
+    #[cfg(feature = "rustls")]
+    tls: Option<std::sync::Arc<rustls::ServerConfig>>,
 }
 
-const FRAGMENT_SIZE: usize = 8*1024;
-
 impl BondTcpListener {
     /// Creates a new `BndTcpListener` which will be bound to the specified address.
     pub fn bind<A: ToSocketAddrs>(addr: A, stream_num: u8) -> IoResult<BondTcpListener> {
-        let listener = TcpListener::bind(addr)?;
+        BondTcpListener::bind_with(addr, stream_num, BondConfig::default())
+    }
+
+    /// Creates a new `BndTcpListener` bound to the specified address, the
+    /// accepted bonds use the given configuration.
+    pub fn bind_with<A: ToSocketAddrs>(addr: A, stream_num: u8, config: BondConfig) -> IoResult<BondTcpListener> {
+        let listener = listen(addr, &config)?;
+        BondTcpListener::from_listeners(vec![listener], stream_num, config)
+    }
+
+    /// Creates a new `BndTcpListener` out of a listener already bound, e.g.
+    /// one handed over by the service manager, the accepted bonds using the
+    /// given configuration.
+    ///
+    /// `accept` blocks unless the listener is in nonblocking mode, in which
+    /// case it fails with `WouldBlock` while no connection is pending, the
+    /// connections of the bonds being established are kept in the meantime.
+    pub fn from_std(listener: TcpListener, stream_num: u8, config: BondConfig) -> IoResult<BondTcpListener> {
+        // Only the connections accepted from now on get the receive buffer.
+        if let Some(size) = sockopt::largest_recv_buffer(&config) {
+            SockRef::from(&listener).set_recv_buffer_size(size)?;
+        }
+        BondTcpListener::from_listeners(vec![listener], stream_num, config)
+    }
+
+    /// Creates a new `BndTcpListener` bound to every one of the specified
+    /// addresses, e.g. those of several interfaces of the host, the accepted
+    /// bonds using the given configuration. The connections of a bond may
+    /// reach the listener through any of its addresses.
+    pub fn bind_multi<A: ToSocketAddrs>(addrs: &[A], stream_num: u8, config: BondConfig) -> IoResult<BondTcpListener> {
+        let listeners = addrs.iter().map(|addr| listen(addr, &config)).collect::<IoResult<Vec<_>>>()?;
+        BondTcpListener::from_listeners(listeners, stream_num, config)
+    }
+
+    fn from_listeners(listeners: Vec<TcpListener>, stream_num: u8, config: BondConfig) -> IoResult<BondTcpListener> {
+        if listeners.is_empty() {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "A BondTcpListener needs at least one address"));
+        }
+        // A single listener is simply accepted from, several are polled.
+        let poller = if listeners.len() > 1 {
+            let poller = polling::Poller::new()?;
+            for (key, listener) in listeners.iter().enumerate() {
+                listener.set_nonblocking(true)?;
+                // SAFETY: the listener is deleted from the poller in `Drop`,
+                // before being closed.
+                unsafe { poller.add_with_mode(listener, polling::Event::readable(key), polling::PollMode::Level)? };
+            }
+            Some(poller)
+        } else {
+            None
+        };
         Ok(BondTcpListener {
-            listener,
-            stream_num,
-            accepted_connections: HashMap::new(),          
+            listeners,
+            poller,
+            joins: Joins::new(stream_num, config),
+            ttl: AtomicU32::new(0),
+            nonblocking: AtomicBool::new(false),
+            #[cfg(feature = "rustls")]
+            tls: None,
         })
-    }    /// Returns the local address that this listener is bound to.
+    }
+
+    /// Creates a new `BndTcpListener` bound to the specified address, every
+    /// accepted connection being secured by TLS with the given configuration
+    /// before it joins a bond.
+    ///
+    /// The identifier of a bond is only exchanged once TLS is established.
+    /// The joining connections of a bond resume the TLS session of the first
+    /// one, as long as `tls` keeps the default session storage. A TLS 1.3
+    /// ticket being used up by every resumption, the first connection is
+    /// handed at least one ticket per joining connection.
+    #[cfg(feature = "rustls")]
+    pub fn bind_tls<A: ToSocketAddrs>(addr: A, stream_num: u8, config: BondConfig, mut tls: std::sync::Arc<rustls::ServerConfig>) -> IoResult<BondTcpListener> {
+        let joins = (stream_num as usize).saturating_sub(1);
+        if tls.send_tls13_tickets < joins {
+            std::sync::Arc::make_mut(&mut tls).send_tls13_tickets = joins;
+        }
+        let mut listener = BondTcpListener::bind_with(addr, stream_num, config)?;
+        listener.tls = Some(tls);
+        Ok(listener)
+    }
+
+    /// Secures an accepted connection when the listener was given a TLS
+    /// configuration.
+    fn secure(&self, stream: TcpStream) -> IoResult<Link<TcpStream>> {
+        #[cfg(feature = "rustls")]
+        if let Some(tls) = self.tls.as_ref() {
+            let conn = rustls::ServerConnection::new(tls.clone()).map_err(std::io::Error::other)?;
+            return Link::tls(stream, conn);
+        }
+        Ok(Link::plain(stream))
+    }
+
+    /// Returns the local address that this listener is bound to, the first
+    /// one when it is bound to several.
     pub fn local_addr(&self) -> IoResult<SocketAddr> {
-        self.listener.local_addr()        
+        self.listeners[0].local_addr()        
+    }
+
+    /// Returns all the local addresses that this listener is bound to.
+    pub fn local_addrs(&self) -> IoResult<Vec<SocketAddr>> {
+        self.listeners.iter().map(TcpListener::local_addr).collect()
+    }
+
+    /// Accepts the next connection reaching any of the listeners, giving it
+    /// the TTL set on the listener.
+    fn next_connection(&self) -> IoResult<(TcpStream, SocketAddr)> {
+        let (stream, addr) = self.poll_listeners()?;
+        // Accepted sockets inherit nonblocking mode on some systems.
+        stream.set_nonblocking(false)?;
+        match self.ttl.load(Ordering::Relaxed) {
+            0 => {}
+            ttl => sockopt::set_ttl(&stream, ttl)?,
+        }
+        Ok((stream, addr))
+    }
+
+    fn poll_listeners(&self) -> IoResult<(TcpStream, SocketAddr)> {
+        let Some(poller) = self.poller.as_ref() else {
+            return self.listeners[0].accept();
+        };
+        let mut events = polling::Events::new();
+        loop {
+            for listener in self.listeners.iter() {
+                match listener.accept() {
+                    Ok(accepted) => return Ok(accepted),
+                    Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            if self.nonblocking.load(Ordering::Relaxed) {
+                return Err(std::io::ErrorKind::WouldBlock.into());
+            }
+            events.clear();
+            poller.wait(&mut events, None)?;
+        }
     }
 
     /// Creates a new independently owned handle to the underlying socket.
@@ -92,471 +244,777 @@ impl BondTcpListener {
 
     /// Accept a new incoming connection from this listener.
     pub fn accept(&mut self) -> IoResult<(BondTcpStream, SocketAddr)> {
-        let r_poller = polling::Poller::new().unwrap();
-        let w_poller = polling::Poller::new().unwrap();
         loop {
-            let mut cid_buf = [0u8; 16];
-            let (mut stream, addr) = self.listener.accept()?;
-            log::debug!("Accepted connection from: {addr}");
-            let n = stream.read(&mut cid_buf)?;
-            log::debug!("Read {n} bytes for CID");
-            let cid = uuid::Uuid::from_bytes_le(cid_buf);
-            log::debug!("Connection Id: {cid}");
-            match self.accepted_connections.remove(&cid) {
-                Some(mut streams) => {                     
-                    if streams.len() + 1 == self.stream_num as usize {
-                        log::debug!("We have already {} connections with {cid} accepting the session", streams.len());
-                        streams.push(stream);                        
-                                                
-                        for (id, s) in streams.iter().enumerate() {
-                            s.set_nonblocking(true)?;
-                            unsafe {
-                                let _ = r_poller.add(s, polling::Event::none(id));
-                                let _ = w_poller.add(s, polling::Event::none(id));
-                            }                                                                              
-                        }
-                        return Ok((BondTcpStream { streams, r_poller, w_poller, next_stream: 0, readable: 0 }, addr));
-                    }
-                    else {
-                        log::debug!("{} connection with {cid}", streams.len() + 1);    
-                        // stream.set_nonblocking(true)?;
-                        // unsafe {
-                        //     let _ = r_poller.add(&stream, polling::Event::none(streams.len()));
-                        //     let _ = w_poller.add(&stream, polling::Event::none(streams.len()));
-                        // }                                                  
-                        streams.push(stream);                
-                        self.accepted_connections.insert(cid, streams);                         
-                    }
-                },
-                None => {
-                    // stream.set_nonblocking(true)?;
-                    // unsafe {
-                    //     let _ = r_poller.add(&stream, polling::Event::none(0));
-                    //     let _ = w_poller.add(&stream, polling::Event::none(0));
-                    // }
-
-                        let ns = self.stream_num.to_le_bytes();                        
-                        log::debug!("Sending # of streams {}", self.stream_num);                    
-                        stream.write_all(&ns)?;                                                                
-                        stream.flush()?;
-
-                    if self.stream_num > 1 {
-                        let cid = uuid::Uuid::new_v4();
-                        let cid_buf = cid.to_bytes_le();
-                        stream.write_all(&cid_buf)?;
-                        stream.flush()?;
-                        log::debug!("First connection with {addr} associating it with cid: {cid}");                    
-                        // Inform the other side about the number of socket to be opened.
-                        log::debug!("Sending Cid");
-                        self.accepted_connections.insert(cid, vec![stream]);                        
-                    } else {
-                        stream.set_nonblocking(true)?;
-                        unsafe {
-                            let _ = r_poller.add(&stream, polling::Event::none(0));
-                            let _ = w_poller.add(&stream, polling::Event::none(0));
-                        }                                                                                                      
-                        return Ok((BondTcpStream { streams: vec![stream], r_poller, w_poller, next_stream: 0, readable: 0 }, addr));                                              
-                    }                    
-                    
-                }
-
+            let (stream, addr) = self.next_connection()?;
+            tracing::debug!(peer = %addr, "Accepted connection");
+            let stream = self.secure(stream)?;
+            if let Some(bond) = self.joins.join(stream, &addr)? {
+                return Ok((bond, addr));
             }
         }
-                        
     }
 
-    /// Returns an iterator over the connections being received on this listener.
+    /// Returns an iterator over the connections being received on this
+    /// listener. It is not supported yet, the iterator only yields an error.
     pub fn incoming(&self) -> Incoming<'_> {
-        // TODO: Implement incoming
-        todo!()
+        // Deliberately unsupported: accepting takes the listener mutably, as
+        // it keeps the bonds being established, while the iterator borrows it.
+        Incoming { _listener: self, done: false }
     }
 
-    /// Sets the value for the `IP_TTL` option on this socket.
-    pub fn set_ttl(&self, _ttl: u32) -> IoResult<()> {
-        // TODO: Implement set_ttl
-        todo!()
+    /// Sets the value for the `IP_TTL` option, or `IPV6_UNICAST_HOPS` on IPv6
+    /// sockets, on every listening socket and on the connections accepted
+    /// from now on.
+    pub fn set_ttl(&self, ttl: u32) -> IoResult<()> {
+        for listener in self.listeners.iter() {
+            sockopt::set_ttl(listener, ttl)?;
+        }
+        self.ttl.store(ttl, Ordering::Relaxed);
+        Ok(())
     }
 
-    /// Gets the value of the `IP_TTL` option for this socket.
+    /// Gets the value of the `IP_TTL` option of the listening sockets, an
+    /// error if they do not agree.
     pub fn ttl(&self) -> IoResult<u32> {
-        // TODO: Implement ttl
-        todo!()
+        agree("IP_TTL", self.listeners.iter().map(sockopt::ttl))
     }
 
-    /// Sets the value for the `SO_REUSEADDR` option on this socket.
-    pub fn set_nonblocking(&self, _nonblocking: bool) -> IoResult<()> {
-        // TODO: Implement set_nonblocking
-        todo!()
+    /// Moves this listener into or out of nonblocking mode, in which `accept`
+    /// fails with `WouldBlock` while no connection is pending. The
+    /// connections of the bonds being established are kept in the meantime.
+    pub fn set_nonblocking(&self, nonblocking: bool) -> IoResult<()> {
+        // Several listeners are always nonblocking, their readiness being polled.
+        if self.poller.is_none() {
+            self.listeners[0].set_nonblocking(nonblocking)?;
+        }
+        self.nonblocking.store(nonblocking, Ordering::Relaxed);
+        Ok(())
     }
 
-    /// Gets the value of the `SO_REUSEADDR` option on this socket.
+    /// Gets the value of the `SO_ERROR` option of every listening socket,
+    /// clearing it, the errors found being reported as one along with the
+    /// index of their listener.
     pub fn take_error(&self) -> IoResult<Option<std::io::Error>> {
-        // TODO: Implement take_error
-        todo!()
+        collect_errors("listener", self.listeners.iter().map(TcpListener::take_error))
+    }
+}
+
+impl Drop for BondTcpListener {
+    fn drop(&mut self) {
+        if let Some(poller) = self.poller.as_ref() {
+            for listener in self.listeners.iter() {
+                let _ = poller.delete(listener);
+            }
+        }
+    }
+}
+
+/// A bond being established by a listener: the connections accepted so far,
+/// in their place in the bond, the session settled with the first one and
+/// the span of the bond.
+struct Pending<T> {
+    links: Vec<Option<Link<T>>>,
+    session: Session,
+    span: Span,
+}
+
+/// The bonds a listener is establishing, the connections accepted so far
+/// being kept under the identifier of their bond until all of them joined.
+///
+/// Joining connections follow the identifier with their index in the bond,
+/// as they may be accepted in another order than they were opened, e.g. when
+/// they reach the listener through different addresses.
+pub(crate) struct Joins<T: Transport> {
+    stream_num: u8,
+    accepted_connections: HashMap<uuid::Uuid, Pending<T>>,
+    config: BondConfig,
+}
+
+impl<T: Transport> Joins<T> {
+    pub(crate) fn new(stream_num: u8, config: BondConfig) -> Joins<T> {
+        Joins { stream_num, accepted_connections: HashMap::new(), config }
+    }
+
+    /// Runs the handshake of a connection accepted from `addr`, returns the
+    /// bond it completes, if any.
+    pub(crate) fn join(&mut self, stream: Link<T>, addr: &impl std::fmt::Debug) -> IoResult<Option<BondStream<T>>> {
+        self.handshake(stream, addr).inspect_err(|_| self.config.handshake_failed())
+    }
+
+    fn handshake(&mut self, mut stream: Link<T>, addr: &impl std::fmt::Debug) -> IoResult<Option<BondStream<T>>> {
+        let mut cid_buf = [0u8; 16];
+        stream.read_exact(&mut cid_buf)?;
+        let cid = uuid::Uuid::from_bytes_le(cid_buf);
+        // The bond stays pending until the connection takes its place in it,
+        // a connection failing to join leaving it as it was.
+        match self.accepted_connections.get_mut(&cid) {
+            Some(pending) => {
+                let entered = pending.span.enter();
+                let mut index = [0u8; 1];
+                stream.read_exact(&mut index)?;
+                let index = index[0] as usize;
+                if index >= pending.links.len() || pending.links[index].is_some() {
+                    return Err(BondError::protocol(
+                        None,
+                        format!("Connection {index} cannot join bond {cid} of {} connections", pending.links.len()),
+                    ));
+                }
+                tracing::debug!(substream = index, peer = ?addr, "Substream connected");
+                if let Some(observer) = self.config.observer.as_ref() {
+                    observer.notify_substream(BondEventKind::SubstreamConnected, Some(cid), index, &stream.stream);
+                }
+                pending.links[index] = Some(stream);
+                let complete = pending.links.iter().all(Option::is_some);
+                drop(entered);
+                if complete {
+                    let Pending { links, session, span } = self.accepted_connections.remove(&cid).expect("the bond is pending");
+                    self.config.add_pending(-1);
+                    let links: Vec<_> = links.into_iter().flatten().collect();
+                    sockopt::configure(&links, &self.config)?;
+                    return Ok(Some(BondStream::new(links, &self.config, session, Some(cid), span)?));
+                }
+            }
+            None => {
+                // The connecting side identifies the first connection of a
+                // bond with an identifier of its own, the listener handing
+                // out the one of the bond.
+                let span = trace::bond_span(None);
+                span.record("peer", field::debug(addr));
+                let entered = span.enter();
+                tracing::debug!(substream = 0, "Substream connected");
+                // The first connection of a bond carries the options proposed by the peer.
+                let mut flags = [0u8; 1];
+                stream.read_exact(&mut flags)?;
+                let (answer, session) = handshake::answer(&self.config, flags[0], &mut stream)?;
+                tracing::debug!(substreams = self.stream_num, options = ?session.options, "Answering the proposal");
+                let mut reply = vec![self.stream_num];
+                reply.extend_from_slice(&answer);
+                stream.write_all(&reply)?;
+                stream.flush()?;
+                if self.stream_num <= 1 {
+                    if let Some(observer) = self.config.observer.as_ref() {
+                        observer.notify_substream(BondEventKind::SubstreamConnected, None, 0, &stream.stream);
+                        observer.notify(BondEventKind::HandshakeCompleted, None);
+                    }
+                    let links = vec![stream];
+                    sockopt::configure(&links, &self.config)?;
+                    drop(entered);
+                    return Ok(Some(BondStream::new(links, &self.config, session, None, span)?));
+                }
+                // Inform the other side about the bond the other connections join.
+                let cid = uuid::Uuid::new_v4();
+                stream.write_all(&cid.to_bytes_le())?;
+                stream.flush()?;
+                span.record("cid", field::display(cid));
+                tracing::debug!("Handshake completed, waiting for the other connections");
+                if let Some(observer) = self.config.observer.as_ref() {
+                    observer.notify_substream(BondEventKind::SubstreamConnected, Some(cid), 0, &stream.stream);
+                    observer.notify(BondEventKind::HandshakeCompleted, Some(cid));
+                }
+                let mut links: Vec<_> = (0..self.stream_num).map(|_| None).collect();
+                links[0] = Some(stream);
+                drop(entered);
+                self.config.add_pending(1);
+                self.accepted_connections.insert(cid, Pending { links, session, span });
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl<T: Transport> Drop for Joins<T> {
+    fn drop(&mut self) {
+        self.config.add_pending(-(self.accepted_connections.len() as i64));
     }
 }
 
 /// An iterator that infinitely accepts connections on a `BndTcpListener`.
 pub struct Incoming<'a> {
     _listener: &'a BondTcpListener,
+    done: bool,
 }
 
 impl<'a> Iterator for Incoming<'a> {
     type Item = IoResult<BondTcpStream>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        // TODO: Implement iterator
-        todo!()
+        // Deliberately yields a single error, see `BondTcpListener::incoming`.
+        if std::mem::replace(&mut self.done, true) {
+            return None;
+        }
+        Some(Err(unsupported("`incoming`")))
     }
 }
 
-/// A bonded TCP stream that aggregates multiple underlying TCP connections.
+/// The side of a bond taken by `BondStream::from_streams`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    /// The side that opened the connections, it proposes the options of the
+    /// bond and its order of the connections is the one kept by both sides.
+    Connector,
+    /// The side that accepted the connections, it settles the options of the
+    /// bond.
+    Acceptor,
+}
+
+/// A bonded stream that aggregates multiple underlying connections.
 ///
-/// This struct represents multiple TCP connections that have been bonded together
+/// This struct represents multiple connections that have been bonded together
 /// to act as a single logical stream. Data written to this stream is distributed
 /// across the underlying connections, and data read from this stream is collected
 /// from all connections.
 ///
+/// The connections can be any [`Transport`], `BondTcpStream` being the bond of
+/// TCP connections.
+pub struct BondStream<T: Transport> {
+    io: Io<T>,
+    /// Handles to the sockets of the substreams, in the order of the bond,
+    /// for the accessors, as the substreams may be driven by another thread.
+    sockets: Vec<OwnedFd>,
+    /// The span of the bond, entered on every call.
+    span: Span,
+}
+
+/// A bonded TCP stream that aggregates multiple underlying TCP connections.
+///
 /// `BondTcpStream` provides the same interface as a standard `TcpStream` but with
 /// the performance benefits of multiple parallel connections.
-pub struct BondTcpStream {
-    streams: std::vec::Vec<TcpStream>,
-    r_poller: polling::Poller,
-    w_poller: polling::Poller,
-    next_stream: usize,
-    readable: usize
+pub type BondTcpStream = BondStream<TcpStream>;
+
+enum Io<T: Transport> {
+    Inline(Box<Bond<T>>),
+    Background(Background),
 }
 
-impl BondTcpStream {
+impl<T: Transport> BondStream<T> {
 
-    /// Opens a TCP connection to a remote host.    
-    pub fn connect<A: ToSocketAddrs>(addr: A) -> IoResult<BondTcpStream> {
-        let r_poller = polling::Poller::new().unwrap();
-        let w_poller = polling::Poller::new().unwrap();
-        let mut addresses = vec![]; 
-        for a in addr.to_socket_addrs().unwrap() {
-            addresses.push(a);
-        }        
-        let tid = uuid::Uuid::new_v4();
-        let mut stream = TcpStream::connect(addresses.as_slice())?;                
-
-        log::debug!("Established first connection, sending challenge");            
-        let _ = stream.write(&tid.to_bytes_le())?;        
-        let _ = stream.flush();
-        let mut len_buf = [0u8; size_of::<u8>()];
-        let _ = stream.read(&mut len_buf)?;                
-        let ns = u8::from_le_bytes(len_buf);
-        log::debug!("conecct>> Listener asking to establish {ns} connections");
-        if ns > 1 {
-            let mut cid_buf = [0u8; 16];
-            stream.read_exact(&mut cid_buf)?;
-
-            log::debug!("BondTcpStream will open {ns} streams");
-            log::debug!("CID: {}", Uuid::from_bytes_le(cid_buf));
+    fn new(links: Vec<Link<T>>, config: &BondConfig, session: Session, cid: Option<Uuid>, span: Span) -> IoResult<BondStream<T>> {
+        let sockets = links.iter().map(|l| l.stream.as_fd().try_clone_to_owned()).collect::<IoResult<_>>()?;
+        let bond = Bond::new(links, config, session, cid, span.clone())?;
+        let io = match config.io_mode {
+            IoMode::Inline => Io::Inline(Box::new(bond)),
+            IoMode::Background { queue_size } => Io::Background(Background::spawn(bond, queue_size)?),
+        };
+        Ok(BondStream { io, sockets, span })
+    }
+
+    fn sockets(&self) -> impl Iterator<Item = SockRef<'_>> {
+        self.sockets.iter().map(SockRef::from)
+    }
+
+    /// Writes `buf` like `write`, every frame carrying it being sent on
+    /// `copies` substreams, or on all of them if there are fewer, so that a
+    /// stalled path does not hold the message up: the peer delivers the
+    /// first copy to arrive and discards the others. Sending more than one
+    /// copy fails with `Unsupported` unless frames are numbered, i.e. either
+    /// side sets `BondConfig::redundancy` or schedules frames by latency.
+    pub fn write_redundant(&mut self, buf: &[u8], copies: usize) -> IoResult<usize> {
+        let _entered = self.span.enter();
+        let numbered = match &self.io {
+            Io::Inline(bond) => bond.numbered,
+            Io::Background(bg) => bg.numbered,
+        };
+        if copies > 1 && !numbered {
+            return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "Copies of frames are only sent when frames are numbered"));
+        }
+        let bufs = [IoSlice::new(buf)];
+        match &mut self.io {
+            Io::Inline(bond) => bond.write_frames(&bufs, Some(copies)),
+            Io::Background(bg) => bg.write_vectored(&bufs, Some(copies)),
+        }
+    }
+
+    /// Returns the traffic of the bond and of each of its substreams, along
+    /// with their round-trip times and the state of their TCP connections.
+    pub fn stats(&self) -> BondStats {
+        let (io, wait_time, mut substreams) = match &self.io {
+            Io::Inline(bond) => (bond.counters, bond.wait_time, bond.substream_stats()),
+            Io::Background(bg) => bg.traffic(),
+        };
+        for (s, socket) in substreams.iter_mut().zip(self.sockets.iter()) {
+            s.tcp = stats::tcp_info(socket);
+        }
+        BondStats { io, wait_time, substreams }
+    }
+
+    /// Gets the value of the `SO_ERROR` option of every substream, clearing
+    /// it, the errors found being reported as one along with the index of
+    /// their substream.
+    pub fn take_error(&self) -> IoResult<Option<std::io::Error>> {
+        collect_errors("substream", self.sockets().map(|s| s.take_error()))
+    }
+
+    /// Bonds connections established beforehand, e.g. by a dialer of the
+    /// application or with custom socket options. The peer must bond the
+    /// other end of the same connections, taking the other role.
+    pub fn from_streams(streams: Vec<T>, role: Role) -> IoResult<BondStream<T>> {
+        BondStream::from_streams_with(streams, role, &BondConfig::default())
+    }
+
+    /// Bonds connections established beforehand, using the given
+    /// configuration for the bond.
+    ///
+    /// Nothing is exchanged on the connections but the handshake of the bond:
+    /// the connector sends its index on every connection, along with the
+    /// options it proposes on the first one, and the acceptor answers on the
+    /// first one with the options settled, thus both sides may give the
+    /// connections in any order.
+    pub fn from_streams_with(streams: Vec<T>, role: Role, config: &BondConfig) -> IoResult<BondStream<T>> {
+        if streams.is_empty() || streams.len() > u8::MAX as usize {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Cannot bond {} connections, 1 to {} are supported", streams.len(), u8::MAX),
+            ));
+        }
+        let mut links = Vec::with_capacity(streams.len());
+        for stream in streams {
+            // The handshake blocks, whatever mode the connections were left in.
+            stream.set_nonblocking(false)?;
+            links.push(Link::plain(stream));
+        }
+        let span = trace::bond_span(None);
+        if let Some(peer) = events::peer_addr(&links[0].stream) {
+            span.record("peer", field::display(peer));
+        }
+        let (links, session) = span.in_scope(|| match role {
+            Role::Connector => BondStream::propose_to(links, config),
+            Role::Acceptor => BondStream::answer_to(links, config),
+        })
+        .inspect_err(|_| config.handshake_failed())?;
+        tracing::debug!(parent: &span, substreams = links.len(), ?role, options = ?session.options, "Handshake completed");
+        sockopt::configure(&links, config)?;
+        if let Some(observer) = config.observer.as_ref() {
+            observer.notify(BondEventKind::HandshakeCompleted, None);
+        }
+        BondStream::new(links, config, session, None, span)
+    }
+
+    /// Sends the index of every connection and proposes the options of the
+    /// bond on the first one.
+    fn propose_to(mut links: Vec<Link<T>>, config: &BondConfig) -> IoResult<(Vec<Link<T>>, Session)> {
+        let ns = links.len() as u8;
+        let (proposal, nonce) = handshake::propose(config)?;
+        for (index, link) in links.iter_mut().enumerate() {
+            let mut hello = vec![index as u8, ns];
+            if index == 0 {
+                hello.extend_from_slice(&proposal);
+            }
+            link.write_all(&hello)?;
+        }
+        let mut flags = [0u8; 1];
+        links[0].read_exact(&mut flags)?;
+        let session = handshake::conclude(config, &proposal, nonce, flags[0], &mut links[0])?;
+        Ok((links, session))
+    }
+
+    /// Puts the connections in the order of the connector, answering its
+    /// proposal on the first one.
+    fn answer_to(links: Vec<Link<T>>, config: &BondConfig) -> IoResult<(Vec<Link<T>>, Session)> {
+        let ns = links.len();
+        let mut ordered: Vec<Option<Link<T>>> = (0..ns).map(|_| None).collect();
+        let mut session = None;
+        for mut link in links {
+            let mut hello = [0u8; 2];
+            link.read_exact(&mut hello)?;
+            let [index, count] = hello.map(usize::from);
+            if count != ns || index >= ns || ordered[index].is_some() {
+                return Err(BondError::mismatch(format!("The peer bonds connection {index} out of {count}, while {ns} connections are given")));
+            }
+            if index == 0 {
+                let mut flags = [0u8; 1];
+                link.read_exact(&mut flags)?;
+                let (answer, s) = handshake::answer(config, flags[0], &mut link)?;
+                link.write_all(&answer)?;
+                session = Some(s);
+            }
+            ordered[index] = Some(link);
+        }
+        // Every index was taken once, the first one included.
+        let links = ordered.into_iter().flatten().collect();
+        Ok((links, session.expect("the first connection carries the proposal")))
+    }
+
+    /// Establishes a bond out of the connections opened by `open`, the first
+    /// one settling the options of the bond and the others joining it.
+    pub(crate) fn dial(config: &BondConfig, mut open: impl FnMut() -> IoResult<Link<T>>) -> IoResult<BondStream<T>> {
+        let span = trace::bond_span(None);
+        let (streams, session, cid) = span.in_scope(|| -> IoResult<_> {
+            let tid = uuid::Uuid::new_v4();
+            let mut stream = open()?;
+            if let Some(peer) = events::peer_addr(&stream.stream) {
+                span.record("peer", field::display(peer));
+            }
+            tracing::debug!(substream = 0, "Substream connected, proposing the options of the bond");
+            if let Some(observer) = config.observer.as_ref() {
+                observer.notify_substream(BondEventKind::SubstreamConnected, None, 0, &stream.stream);
+            }
+            let (proposal, nonce) = handshake::propose(config)?;
+            let mut hello = tid.to_bytes_le().to_vec();
+            hello.extend_from_slice(&proposal);
+            stream.write_all(&hello)?;        
+            let _ = stream.flush();
+            let mut reply = [0u8; 2];
+            stream.read_exact(&mut reply)?;                
+            let ns = reply[0];
+            let session = handshake::conclude(config, &proposal, nonce, reply[1], &mut stream)?;
             let mut streams = vec![stream];
-        
-            for _ in 1..ns {           
-                log::debug!("Establishing another connection");
-                let mut s = TcpStream::connect(addresses.as_slice())?;                            
-                log::debug!("Sending UUID: {}", Uuid::from_bytes_le(cid_buf));
-                let _ = s.write(&cid_buf)?;
-                let _ = s.flush();
-                streams.push(s);            
+            let mut cid = None;
+            if ns > 1 {
+                let mut cid_buf = [0u8; 16];
+                streams[0].read_exact(&mut cid_buf)?;
+                cid = Some(Uuid::from_bytes_le(cid_buf));
+                span.record("cid", field::display(Uuid::from_bytes_le(cid_buf)));
             }
-            
-            for (id, s) in streams.iter().enumerate() {
-                let _ = s.set_nonblocking(true);
-                let _ = s.set_nodelay(true);
-                unsafe {
-                    let _ = r_poller.add(s, polling::Event::none(id));
-                    let _ = w_poller.add(s, polling::Event::none(id));        
-                }
+            tracing::debug!(substreams = ns, options = ?session.options, "Handshake completed");
+            if let Some(observer) = config.observer.as_ref() {
+                observer.notify(BondEventKind::HandshakeCompleted, cid);
             }
-            Ok (BondTcpStream { streams, r_poller, w_poller, next_stream: 0, readable: 0 })        
-        } else {
-            let _ = stream.set_nonblocking(true);
-            let _ = stream.set_nodelay(true);
-                unsafe {
-                    let _ = r_poller.add(&stream, polling::Event::none(0));
-                    let _ = w_poller.add(&stream, polling::Event::none(0));        
+            if let Some(cid) = cid {
+                for index in 1..ns {           
+                    let mut s = open()?;                            
+                    tracing::debug!(substream = index, "Substream connected");
+                    if let Some(observer) = config.observer.as_ref() {
+                        observer.notify_substream(BondEventKind::SubstreamConnected, Some(cid), index as usize, &s.stream);
+                    }
+                    let mut join = cid.to_bytes_le().to_vec();
+                    join.push(index);
+                    s.write_all(&join)?;
+                    let _ = s.flush();
+                    streams.push(s);            
                 }
-            Ok (BondTcpStream { streams: vec![stream], r_poller, w_poller, next_stream: 0, readable: 0 })        
+            }
+            Ok((streams, session, cid))
+        })
+        .inspect_err(|_| config.handshake_failed())?;
+        BondStream::new(streams, config, session, cid, span)
+    }
+
+    /// Returns the number of system calls issued so far on this stream.
+    pub fn io_counters(&self) -> IoCounters {
+        match &self.io {
+            Io::Inline(bond) => bond.counters,
+            Io::Background(bg) => bg.io_counters(),
+        }
+    }
+}
+
+/// Returns the value shared by all the sockets, or an error naming the option
+/// they disagree on.
+fn agree<V: PartialEq + std::fmt::Debug>(option: &str, values: impl Iterator<Item = IoResult<V>>) -> IoResult<V> {
+    let values = values.collect::<IoResult<Vec<V>>>()?;
+    match values.iter().all(|v| *v == values[0]) {
+        true => Ok(values.into_iter().next().expect("there is at least one socket")),
+        false => Err(std::io::Error::other(format!("The sockets disagree on {option}: {values:?}"))),
+    }
+}
+
+/// Merges the pending errors of several sockets into one, keeping the kind
+/// of the first one and naming every socket by its index.
+fn collect_errors(what: &str, errors: impl Iterator<Item = IoResult<Option<std::io::Error>>>) -> IoResult<Option<std::io::Error>> {
+    let mut found = Vec::new();
+    for (index, error) in errors.enumerate() {
+        if let Some(e) = error? {
+            found.push((index, e));
+        }
+    }
+    let Some(kind) = found.first().map(|(_, e)| e.kind()) else {
+        return Ok(None);
+    };
+    let message = found.iter().map(|(index, e)| format!("{what} {index}: {e}")).collect::<Vec<_>>().join(", ");
+    Ok(Some(std::io::Error::new(kind, message)))
+}
+
+/// Returns the failure to resolve the address to connect to.
+fn resolution(error: std::io::Error) -> std::io::Error {
+    BondError::Resolution { error: std::sync::Arc::new(error) }.into()
+}
+
+/// Returns the error of an operation bonds do not support.
+fn unsupported(operation: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::Unsupported, format!("{operation} is not supported by bonds yet"))
+}
+
+fn socket_addr(addr: socket2::SockAddr) -> IoResult<SocketAddr> {
+    addr.as_socket().ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "Not an IP socket address"))
+}
+
+/// Listens on the first address of `addr` that can be bound, the socket
+/// being given the largest receive buffer of the substreams beforehand, as
+/// the window scale of the connections it accepts is settled before they
+/// are handed over and their own options set.
+fn listen<A: ToSocketAddrs>(addr: A, config: &BondConfig) -> IoResult<TcpListener> {
+    let mut last_err = None;
+    for addr in addr.to_socket_addrs()? {
+        let socket = socket2::Socket::new(socket2::Domain::for_address(addr), socket2::Type::STREAM, Some(socket2::Protocol::TCP))?;
+        // As `TcpListener::bind` does.
+        #[cfg(unix)]
+        socket.set_reuse_address(true)?;
+        if let Some(size) = sockopt::largest_recv_buffer(config) {
+            socket.set_recv_buffer_size(size)?;
+        }
+        match socket.bind(&addr.into()).and_then(|()| socket.listen(128)) {
+            Ok(()) => return Ok(socket.into()),
+            Err(e) => last_err = Some(e),
+        }
+    }
+    Err(last_err.unwrap_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "No address to listen on")))
+}
+
+/// Opens a TCP connection to the first reachable address of `target`, the
+/// socket being given `options` and bound to `local` beforehand, in which
+/// case only the addresses of the same family are tried.
+fn connect_from(local: Option<SocketAddr>, options: Option<&SocketOptions>, target: &[SocketAddr]) -> IoResult<TcpStream> {
+    let mut last_err = None;
+    for addr in target.iter().filter(|a| local.is_none_or(|l| a.is_ipv4() == l.is_ipv4())) {
+        let socket = socket2::Socket::new(socket2::Domain::for_address(*addr), socket2::Type::STREAM, Some(socket2::Protocol::TCP))?;
+        if let Some(options) = options {
+            sockopt::apply(&socket, options)?;
+        }
+        if let Some(local) = local {
+            socket.bind(&local.into())?;
+        }
+        match socket.connect(&(*addr).into()) {
+            Ok(()) => return Ok(socket.into()),
+            Err(e) => last_err = Some(e),
+        }
+    }
+    Err(last_err.unwrap_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("No address to connect to from {local:?}"))
+    }))
+}
+
+impl BondTcpStream {
+    /// Opens a TCP connection to a remote host.    
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> IoResult<BondTcpStream> {
+        BondTcpStream::connect_with(addr, &BondConfig::default())
+    }
+
+    /// Opens a TCP connection to a remote host, using the given configuration for the bond.
+    pub fn connect_with<A: ToSocketAddrs>(addr: A, config: &BondConfig) -> IoResult<BondTcpStream> {
+        let addresses = addr.to_socket_addrs().map_err(resolution)?.collect();
+        BondTcpStream::connect_links(&[addresses], config, |stream| Ok(Link::plain(stream)))
+    }
+
+    /// Opens TCP connections to several endpoints of the same remote host,
+    /// e.g. the addresses of its interfaces, using the given configuration
+    /// for the bond. Substreams are spread round-robin over the endpoints,
+    /// the first one opening the bond.
+    pub fn connect_multi<A: ToSocketAddrs>(endpoints: &[A], config: &BondConfig) -> IoResult<BondTcpStream> {
+        if endpoints.is_empty() {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "A bond needs at least one endpoint"));
         }
+        let targets = endpoints
+            .iter()
+            .map(|e| e.to_socket_addrs().map(Iterator::collect).map_err(resolution))
+            .collect::<IoResult<Vec<Vec<SocketAddr>>>>()?;
+        BondTcpStream::connect_links(&targets, config, |stream| Ok(Link::plain(stream)))
+    }
+
+    /// Opens a TCP connection to a remote host, every connection of the bond
+    /// being secured by TLS with the given configuration and server name.
+    ///
+    /// The joining connections resume the TLS session of the first one, as
+    /// long as `tls` keeps the default session resumption.
+    #[cfg(feature = "rustls")]
+    pub fn connect_tls<A: ToSocketAddrs>(
+        addr: A,
+        server_name: rustls::pki_types::ServerName<'static>,
+        config: &BondConfig,
+        tls: std::sync::Arc<rustls::ClientConfig>,
+    ) -> IoResult<BondTcpStream> {
+        let addresses = addr.to_socket_addrs().map_err(resolution)?.collect();
+        BondTcpStream::connect_links(&[addresses], config, |stream| {
+            let conn = rustls::ClientConnection::new(tls.clone(), server_name.clone()).map_err(std::io::Error::other)?;
+            Link::tls(stream, conn)
+        })
+    }
+
+    /// Establishes a bond, substream `i` connecting to the first reachable
+    /// address of target `i` modulo the number of targets, and every
+    /// connection going through `secure` before anything is exchanged on it.
+    fn connect_links(targets: &[Vec<SocketAddr>], config: &BondConfig, secure: impl Fn(TcpStream) -> IoResult<Link<TcpStream>>) -> IoResult<BondTcpStream> {
+        let mut next = 0;
+        BondStream::dial(config, || {
+            let target = &targets[next % targets.len()];
+            let local = match config.local_addrs.len() {
+                0 => None,
+                n => Some(config.local_addrs[next % n]),
+            };
+            let options = config.socket_options_of(next);
+            next += 1;
+            let stream = match (local, options) {
+                (None, None) => TcpStream::connect(target.as_slice())?,
+                _ => connect_from(local, options, target)?,
+            };
+            let _ = stream.set_nodelay(true);
+            secure(stream)
+        })
     }
 
     /// Opens a TCP connection to a remote host with a timeout.
     pub fn connect_timeout(_addr: &SocketAddr, _timeout: Duration) -> IoResult<BondTcpStream> {
-        // TODO: Implement connect_timeout
-        todo!()
+        // Deliberately unsupported: a bond is made of several connections and a
+        // handshake, which the timeout of a single connection does not bound.
+        Err(unsupported("`connect_timeout`"))
     }
 
-    /// Returns the socket address of the remote peer of this TCP connection.
+    /// Returns the socket address of the remote peer of the substream that
+    /// opened the bond, the substreams of a bond coming from different ports
+    /// if not addresses.
     pub fn peer_addr(&self) -> IoResult<SocketAddr> {
-        // TODO: Implement peer_addr
-        todo!()
+        socket_addr(SockRef::from(&self.sockets[0]).peer_addr()?)
     }
 
-    /// Returns the socket address of the local half of this TCP connection.
+    /// Returns the socket address of the local half of the substream that
+    /// opened the bond.
     pub fn local_addr(&self) -> IoResult<SocketAddr> {
-        // TODO: Implement local_addr
-        todo!()
+        socket_addr(SockRef::from(&self.sockets[0]).local_addr()?)
+    }
+
+    /// Returns the socket address of the remote peer of every substream, in
+    /// the order of the bond, which both sides share.
+    pub fn substream_peer_addrs(&self) -> IoResult<Vec<SocketAddr>> {
+        self.sockets().map(|s| socket_addr(s.peer_addr()?)).collect()
+    }
+
+    /// Returns the socket address of the local half of every substream, in
+    /// the order of the bond.
+    pub fn substream_local_addrs(&self) -> IoResult<Vec<SocketAddr>> {
+        self.sockets().map(|s| socket_addr(s.local_addr()?)).collect()
     }
 
     /// Shuts down the read, write, or both halves of this connection.
     pub fn shutdown(&self, _how: std::net::Shutdown) -> IoResult<()> {
-        // TODO: Implement shutdown
-        todo!()
+        // Deliberately unsupported: frames may still be scheduled, or queued for
+        // the I/O thread in background mode, when the substreams are shut down.
+        Err(unsupported("`shutdown`"))
     }
 
     /// Creates a new independently owned handle to the underlying socket.
     pub fn try_clone(&self) -> IoResult<BondTcpStream> {
-        // TODO: Implement try_clone
-        todo!()
+        // Deliberately unsupported: the buffers and the frame numbering of the
+        // bond would be shared by both handles.
+        Err(unsupported("`try_clone`"))
     }
 
     /// Sets the read timeout to the timeout specified.
     pub fn set_read_timeout(&self, _dur: Option<Duration>) -> IoResult<()> {
-        // TODO: Implement set_read_timeout
-        todo!()
+        // Deliberately unsupported: the substreams are nonblocking and the bond
+        // waits on all of them at once, so their own timeouts never apply.
+        Err(unsupported("`set_read_timeout`"))
     }
 
     /// Sets the write timeout to the timeout specified.
     pub fn set_write_timeout(&self, _dur: Option<Duration>) -> IoResult<()> {
-        // TODO: Implement set_write_timeout
-        todo!()
+        // Deliberately unsupported, for the same reason as `set_read_timeout`.
+        Err(unsupported("`set_write_timeout`"))
     }
 
     /// Returns the read timeout of this socket.
     pub fn read_timeout(&self) -> IoResult<Option<Duration>> {
-        // TODO: Implement read_timeout
-        todo!()
+        // Deliberately unsupported, for the same reason as `set_read_timeout`.
+        Err(unsupported("`read_timeout`"))
     }
 
     /// Returns the write timeout of this socket.
     pub fn write_timeout(&self) -> IoResult<Option<Duration>> {
-        // TODO: Implement write_timeout
-        todo!()
+        // Deliberately unsupported, for the same reason as `set_read_timeout`.
+        Err(unsupported("`write_timeout`"))
     }
 
     /// Receives data on the socket from the remote address to which it is connected.
     pub fn peek(&self, _buf: &mut [u8]) -> IoResult<usize> {
-        // TODO: Implement peek
-        todo!()
+        // Deliberately unsupported: the sockets hold frames rather than the
+        // payload, which is only reassembled across substreams when read.
+        Err(unsupported("`peek`"))
     }
 
-    /// Sets the value of the `TCP_NODELAY` option on this socket.
-    pub fn set_nodelay(&self, _nodelay: bool) -> IoResult<()> {
-        // TODO: Implement set_nodelay
-        todo!()
+    /// Sets the value of the `TCP_NODELAY` option on every substream.
+    pub fn set_nodelay(&self, nodelay: bool) -> IoResult<()> {
+        self.sockets().try_for_each(|s| s.set_tcp_nodelay(nodelay))
     }
 
-    /// Gets the value of the `TCP_NODELAY` option on this socket.
+    /// Gets the value of the `TCP_NODELAY` option of the substreams, an error
+    /// if they do not agree.
     pub fn nodelay(&self) -> IoResult<bool> {
-        // TODO: Implement nodelay
-        todo!()
+        agree("TCP_NODELAY", self.sockets().map(|s| s.tcp_nodelay()))
     }
 
-    /// Sets the value for the `IP_TTL` option on this socket.
-    pub fn set_ttl(&self, _ttl: u32) -> IoResult<()> {
-        // TODO: Implement set_ttl
-        todo!()
+    /// Sets the value for the `IP_TTL` option, or `IPV6_UNICAST_HOPS` on IPv6
+    /// substreams, on every substream.
+    pub fn set_ttl(&self, ttl: u32) -> IoResult<()> {
+        self.sockets.iter().try_for_each(|s| sockopt::set_ttl(s, ttl))
     }
 
-    /// Gets the value of the `IP_TTL` option for this socket.
+    /// Gets the value of the `IP_TTL` option of the substreams, an error if
+    /// they do not agree.
     pub fn ttl(&self) -> IoResult<u32> {
-        // TODO: Implement ttl
-        todo!()
-    }
-
-    /// Get the value of the `SO_ERROR` option on this socket.
-    pub fn take_error(&self) -> IoResult<Option<std::io::Error>> {
-        // TODO: Implement take_error
-        todo!()
+        agree("IP_TTL", self.sockets.iter().map(sockopt::ttl))
     }
 
     /// Moves this TCP stream into or out of nonblocking mode.
     pub fn set_nonblocking(&self, _nonblocking: bool) -> IoResult<()> {
-        // TODO: Implement set_nonblocking
-        todo!()
-    }
-
-    fn write_loop(&mut self, buf: &[u8]) -> IoResult<usize> {   
-        log::debug!("write_loop for {} bytes", buf.len());
-        let n = match self.streams[self.next_stream].write(buf) {
-            Ok(n) => if n > 0 { n } else { return Ok(0) },
-            Err(e) => {
-                if e.kind() == std::io::ErrorKind::WouldBlock {
-                    0
-                } else { return Err(e) }
-            }
-        };
-        
-        if n < buf.len() {
-            let mut index = n;
-            let mut events = polling::Events::new();
-            loop {
-                events.clear();
-                let _ = self.w_poller.modify(&self.streams[self.next_stream], polling::Event::writable(self.next_stream));                
-                log::trace!("Polling for writing data");
-                let n = self.w_poller.wait(&mut events, None)?;
-                log::trace!("Polled {} events", n);
-                for e in events.iter() {
-                    log::trace!("{:?}", e);
-                    if e.key == self.next_stream {
-                        log::trace!("writing buf[{}..{}]",index, buf.len());
-                        let n = self.streams[self.next_stream].write(&buf[index..buf.len()])?;
-                        log::trace!("Actually wrote {n} bytes");
-                        if n == 0 {                            
-                            return Ok(0)
-                        }                        
-                        index += n;                    
-                    }
-                    log::trace!("Index: {index} buf.len(): {}", buf.len());
-                    if index == buf.len() {
-                        return Ok   (buf.len())
-                    }
-                }                                    
-            }
-        }            
-        Ok(buf.len())
-    }
-
-    fn read_loop(&mut self, buf: &mut [u8]) -> IoResult<usize> {
-        log::debug!("read_loop for {} bytes", buf.len());
-        let mut n = match self.streams[self.next_stream].read(buf) {
-            Ok(n) => if n > 0 { n } else { return Ok(0) },
-            Err(e) => {
-                if e.kind() == std::io::ErrorKind::WouldBlock {
-                    0
-                } else { return Err(e) }
-            }
-        };
-        
-        log::trace!("read_loop>> Read {} bytes", n);
-        let len = buf.len();        
-        let mut events = polling::Events::new();
-        while n < len {
-            log::trace!("read_loop>> Looping to read another {} bytes", len - n);
-            events.clear();
-            self.r_poller.modify(
-                &self.streams[self.next_stream], 
-                polling::Event::readable(self.next_stream))?;
-            let _ = self.r_poller.wait(&mut events, None)?;
-            for e in events.iter() {
-                if e.key == self.next_stream {
-                    let rb = self.streams[self.next_stream].read(&mut buf[n..len])?; 
-                    if rb != 0 {
-                        n += rb;
-                    } else {
-                        return Ok(0)
-                    }
-                    log::trace!("read_loop>> Thus far, read  {} bytes", n);                   
-                }
-            }
+        // Deliberately unsupported: the substreams already are nonblocking, the
+        // bond blocking on its poller until it can move whole frames.
+        Err(unsupported("`set_nonblocking`"))
+    }
+}
+
+impl<T: Transport> std::io::Read for BondStream<T> {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        self.read_vectored(&mut [IoSliceMut::new(buf)])
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> IoResult<usize> {
+        let _entered = self.span.enter();
+        match &mut self.io {
+            Io::Inline(bond) => bond.read_frames(bufs),
+            Io::Background(bg) => bg.read_vectored(bufs),
         }
-        log::debug!("read_loop>> Read a total of  {} bytes", n);                   
-        Ok(n)
-    }
-
-    fn read_frame_len(&mut self) -> IoResult<usize> {        
-        let mut len_bs = [0u8; 4]; 
-        let n = self.read_loop(&mut len_bs)?;
-        log::debug!("read_frame_len>> read-loop read {} bytes", n);
-        if n > 0 {            
-            let len = u32::from_le_bytes(len_bs) as usize;
-            Ok(len)
-        } else { Ok(0) }
-    }
-
-    fn read_readable(&mut self, buf: &mut [u8]) -> IoResult<usize> {
-        if self.readable > 0  {        
-            let len = std::cmp::min(self.readable, buf.len());    
-            let n = self.read_loop(&mut buf[0..len])?;
-            if n == self.readable {               
-                self.readable = 0;
-                self.next_stream = (self.next_stream + 1) % self.streams.len();                
-            } else {
-                self.readable -= n;
-            }
-            log::debug!("read_readable read {} bytes", n);
-            Ok(n)
-        } else {
-            log::debug!("read_readable read 0 bytes");
-            Ok(0)
+    }
+}
+
+impl<T: Transport> std::io::BufRead for BondStream<T> {
+    /// Returns the buffered payload of the current frame, reading from the
+    /// substream only when nothing is buffered yet. An empty slice signals
+    /// that the bond was closed.
+    fn fill_buf(&mut self) -> IoResult<&[u8]> {
+        let _entered = self.span.enter();
+        match &mut self.io {
+            Io::Inline(bond) => bond.fill_buf(),
+            Io::Background(bg) => bg.fill_buf(),
         }
-    }    
+    }
 
+    fn consume(&mut self, amt: usize) {
+        match &mut self.io {
+            Io::Inline(bond) => bond.consume(amt),
+            Io::Background(bg) => bg.consume(amt),
+        }
+    }
 }
 
-impl std::io::Read for BondTcpStream {
-    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {                
-        log::debug!("Reading {} bytes and starting with  stream {}", buf.len(), self.next_stream);
-        let buf_len = buf.len();
-        let mut n = self.read_readable(buf)?;
-        log::trace!("Read leftover: {} bytes", n);
-        while n < buf.len() {
-            let len = self.read_frame_len()?;
-            log::trace!("Frame Len: {len}");
-            if len > buf.len() - n {
-                log::trace!("Reading the last: {} bytes", (buf.len() - n));
-                self.readable = len - (buf.len() - n);
-                log::trace!("Leftover after I read: {} bytes", self.readable);
-                let rb = self.read_loop(&mut buf[n..buf_len])?;
-                if rb == 0 { return Ok(0) }
-                else {
-                    n += rb;
-                }
-            } else {
-                // Here we read the full frame from a stream.
-                self.readable = 0;
-                let rb = self.read_loop(&mut buf[n..(n+len)])?;
-                if rb == 0 { return Ok(0) }
-                else {
-                    n += rb;
-                }
-                log::trace!("Read so far: {} bytes", n);
-                self.next_stream = (self.next_stream + 1) % self.streams.len();                
-                log::trace!("Next read will be from stream: {}", self.next_stream);
-            }
-        }       
-        log::debug!("Read  {} bytes, next will read from stream {}/{}\n", buf.len(), self.next_stream, self.streams.len());
-        
-        Ok(buf.len())
+impl<T: Transport> std::io::Write for BondStream<T> {
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        self.write_vectored(&[IoSlice::new(buf)])
     }
-}
 
-impl std::io::Write for BondTcpStream {
-
-
-    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {        
-        log::debug!("Writing {} bytes", buf.len());
-        if buf.len() <= FRAGMENT_SIZE {
-            log::trace!("Writing in one shot using stream {}", self.next_stream);
-            let len_bs = (buf.len() as u32).to_le_bytes();                        
-            let _ = self.write_loop(&len_bs)?;
-            let _ = self.write_loop(buf)?;            
-            self.next_stream = (self.next_stream +1 ) % self.streams.len();
-        } else {            
-            log::trace!("Fragmenting write");
-            let mut sup = FRAGMENT_SIZE;
-            let mut k = 0;                    
-            while sup < buf.len() {          
-                log::trace!("Writing fragment {k}");                      
-                let inf = k * FRAGMENT_SIZE;
-                k += 1;
-                sup = std::cmp::min(k*FRAGMENT_SIZE, buf.len());               
-                let len_bs = ((sup - inf) as u32).to_le_bytes();
-                let _ = self.write_loop(&len_bs)?;
-                let n = self.write_loop(&buf[inf..sup])?;
-                if n == 0 { return Ok(0) }         
-                self.next_stream = (self.next_stream +1 ) % self.streams.len();                       
-            }            
-        }        
-        Ok(buf.len())         
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> IoResult<usize> {
+        let _entered = self.span.enter();
+        match &mut self.io {
+            Io::Inline(bond) => bond.write_frames(bufs, None),
+            Io::Background(bg) => bg.write_vectored(bufs, None),
+        }
     }
 
+    /// In inline mode frames are handed to the substreams before `write`
+    /// returns, thus this is a no-op. In background mode it blocks until the
+    /// I/O thread has written everything queued so far.
     fn flush(&mut self) -> IoResult<()> {
-        // TODO: Implement flush
-        todo!()
+        let _entered = self.span.enter();
+        match &mut self.io {
+            Io::Inline(_) => Ok(()),
+            Io::Background(bg) => bg.flush(),
+        }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,70 @@
+use std::io::Result as IoResult;
+use std::os::unix::net::{SocketAddr, UnixListener, UnixStream};
+use std::path::Path;
+
+use crate::bond_tcp::{BondStream, Joins};
+use crate::config::BondConfig;
+use crate::link::Link;
+
+/// A Unix domain socket listener that bonds multiple connections.
+///
+/// The counterpart of `BondTcpListener` for processes on the same host, with
+/// the same handshake and framing: the connections of a bond are matched by
+/// the identifier the listener hands to the first one, and spreading a bond
+/// over several connections spreads its traffic over as many socket buffers.
+pub struct BondUnixListener {
+    listener: UnixListener,
+    joins: Joins<UnixStream>,
+}
+
+/// A bonded stream that aggregates multiple Unix domain socket connections.
+pub type BondUnixStream = BondStream<UnixStream>;
+
+impl BondUnixListener {
+    /// Creates a new `BondUnixListener` bound to the specified path.
+    pub fn bind<P: AsRef<Path>>(path: P, stream_num: u8) -> IoResult<BondUnixListener> {
+        BondUnixListener::bind_with(path, stream_num, BondConfig::default())
+    }
+
+    /// Creates a new `BondUnixListener` bound to the specified path, the
+    /// accepted bonds use the given configuration.
+    pub fn bind_with<P: AsRef<Path>>(path: P, stream_num: u8, config: BondConfig) -> IoResult<BondUnixListener> {
+        BondUnixListener::from_std(UnixListener::bind(path)?, stream_num, config)
+    }
+
+    /// Creates a new `BondUnixListener` out of a listener already bound, the
+    /// accepted bonds using the given configuration.
+    pub fn from_std(listener: UnixListener, stream_num: u8, config: BondConfig) -> IoResult<BondUnixListener> {
+        Ok(BondUnixListener { listener, joins: Joins::new(stream_num, config) })
+    }
+
+    /// Returns the local address that this listener is bound to.
+    pub fn local_addr(&self) -> IoResult<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Accept a new incoming bond from this listener, along with the address
+    /// of the connection completing it.
+    pub fn accept(&mut self) -> IoResult<(BondUnixStream, SocketAddr)> {
+        loop {
+            let (stream, addr) = self.listener.accept()?;
+            tracing::debug!(peer = ?addr, "Accepted connection");
+            if let Some(bond) = self.joins.join(Link::plain(stream), &addr)? {
+                return Ok((bond, addr));
+            }
+        }
+    }
+}
+
+impl BondUnixStream {
+    /// Opens a Unix domain socket connection to the specified path.
+    pub fn connect<P: AsRef<Path>>(path: P) -> IoResult<BondUnixStream> {
+        BondUnixStream::connect_with(path, &BondConfig::default())
+    }
+
+    /// Opens a Unix domain socket connection to the specified path, using the
+    /// given configuration for the bond.
+    pub fn connect_with<P: AsRef<Path>>(path: P, config: &BondConfig) -> IoResult<BondUnixStream> {
+        BondStream::dial(config, || Ok(Link::plain(UnixStream::connect(path.as_ref())?)))
+    }
+}
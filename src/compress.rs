@@ -0,0 +1,107 @@
+use std::io::{IoSlice, Result as IoResult};
+
+use crate::config::Compression;
+use crate::trace::io_trace;
+
+/// The zstd level fragments are compressed with, favouring speed as frames
+/// are compressed on the I/O path.
+#[cfg(feature = "zstd")]
+const ZSTD_LEVEL: i32 = 1;
+
+/// Compresses the payload of outgoing frames, reusing its buffers and
+/// compression context from one fragment to the next.
+#[derive(Default)]
+pub(crate) struct Deflater {
+    compression: Compression,
+    input: Vec<u8>,
+    output: Vec<u8>,
+    #[cfg(feature = "zstd")]
+    zstd: Option<zstd::bulk::Compressor<'static>>,
+}
+
+impl Deflater {
+    pub(crate) fn new(compression: Compression) -> IoResult<Deflater> {
+        Ok(Deflater {
+            compression,
+            input: Vec::new(),
+            output: Vec::new(),
+            #[cfg(feature = "zstd")]
+            zstd: match compression {
+                Compression::Zstd => Some(zstd::bulk::Compressor::new(ZSTD_LEVEL)?),
+                _ => None,
+            },
+        })
+    }
+
+    /// Returns the compressed payload, or `None` if compression is disabled
+    /// or does not make the payload any smaller, in which case the fragment
+    /// is sent as is.
+    pub(crate) fn deflate(&mut self, payload: &[IoSlice<'_>]) -> Option<&[u8]> {
+        if self.compression == Compression::None {
+            return None;
+        }
+        let input: &[u8] = match payload {
+            [p] => p,
+            _ => {
+                self.input.clear();
+                payload.iter().for_each(|p| self.input.extend_from_slice(p));
+                &self.input
+            }
+        };
+        let n = match self.compression {
+            Compression::None => None,
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => {
+                self.output.resize(lz4_flex::block::get_maximum_output_size(input.len()), 0);
+                lz4_flex::block::compress_into(input, &mut self.output).ok()
+            }
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => {
+                self.output.resize(zstd::zstd_safe::compress_bound(input.len()), 0);
+                let zstd = self.zstd.as_mut()?;
+                zstd.compress_to_buffer(input, self.output.as_mut_slice()).ok()
+            }
+        }?;
+        if n >= input.len() {
+            io_trace!(len = input.len(), "Fragment is incompressible, sending it as is");
+            return None;
+        }
+        Some(&self.output[..n])
+    }
+}
+
+/// Decompresses the payload of incoming frames.
+pub(crate) struct Inflater {
+    compression: Compression,
+    #[cfg(feature = "zstd")]
+    zstd: Option<zstd::bulk::Decompressor<'static>>,
+}
+
+impl Inflater {
+    pub(crate) fn new(compression: Compression) -> IoResult<Inflater> {
+        Ok(Inflater {
+            compression,
+            #[cfg(feature = "zstd")]
+            zstd: match compression {
+                Compression::Zstd => Some(zstd::bulk::Decompressor::new()?),
+                _ => None,
+            },
+        })
+    }
+
+    /// Decompresses `input` into `output`, returns the number of bytes
+    /// written to `output` or a description of why the input is invalid.
+    #[cfg_attr(not(any(feature = "lz4", feature = "zstd")), allow(unused_variables))]
+    pub(crate) fn inflate(&mut self, input: &[u8], output: &mut [u8]) -> Result<usize, String> {
+        match self.compression {
+            Compression::None => Err("compression was not negotiated".into()),
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => lz4_flex::block::decompress_into(input, output).map_err(|e| e.to_string()),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => match self.zstd.as_mut() {
+                Some(zstd) => zstd.decompress_to_buffer(input, output).map_err(|e| e.to_string()),
+                None => Err("no zstd context".into()),
+            },
+        }
+    }
+}
@@ -0,0 +1,280 @@
+use crate::events::Observer;
+
+/// How the substreams of a bond are driven.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IoMode {
+    /// Substream I/O is performed by the calling thread, within `read` and
+    /// `write`. A substream whose send buffer is full blocks the writer until
+    /// it drains, even if the other substreams are idle.
+    #[default]
+    Inline,
+    /// A dedicated thread drives all the substreams, while `read` and `write`
+    /// only move data to and from bounded in-memory queues, blocking when
+    /// the queue to read from is empty or the one to write to is full.
+    Background {
+        /// The capacity in bytes of each of the two queues, it is raised to
+        /// the fragment size if lower.
+        queue_size: usize,
+    },
+}
+
+/// How the frames of a bond are spread over its substreams.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Scheduler {
+    /// Every substream carries one frame in turn. The reader is held up by
+    /// the slowest path, as each frame has to wait for the ones before it.
+    #[default]
+    RoundRobin,
+    /// Every frame goes on the substream where it is expected to arrive
+    /// first, in the style of the BLEST and ECF schedulers of MPTCP. The
+    /// arrival is predicted out of the round-trip time of the substream, its
+    /// delivery rate, and the bytes handed to it that the peer has not read
+    /// yet, all measured with pings sent every `BondConfig::ping_interval`,
+    /// or every 100ms if unset. Frames are scheduled round-robin until every
+    /// substream has answered a ping.
+    Latency,
+}
+
+/// The mechanism used to perform substream I/O.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// Non-blocking reads and writes, issued when the substreams are reported
+    /// ready by a single edge-triggered poller.
+    #[default]
+    Poll,
+    /// Reads and writes on registered buffers, submitted in batches across
+    /// all the substreams through io_uring. Frames are copied to the
+    /// registered buffers, header and payload being written by one operation.
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    IoUring,
+}
+
+/// Configuration of a bond, used by `BondTcpStream::connect_with` on the
+/// connecting side and by `BondTcpListener::bind_with` on the accepting one.
+#[derive(Debug, Clone, Default)]
+pub struct BondConfig {
+    /// How the substreams of the bond are driven.
+    pub io_mode: IoMode,
+    /// Registers the substreams in oneshot mode, re-arming them before every
+    /// wait, rather than once for both directions in edge-triggered mode.
+    /// Oneshot mode is used anyway when edge-triggered mode is not supported,
+    /// it is otherwise only meant as the baseline the system calls counted by
+    /// `BondTcpStream::io_counters` are compared against.
+    pub oneshot: bool,
+    /// The mechanism used to perform substream I/O.
+    pub backend: Backend,
+    /// The integrity check asked for on every frame. It is negotiated when
+    /// the bond is established, frames being checked if either side asks.
+    pub checksum: Checksum,
+    /// The compression asked for on the payload of frames. It is negotiated
+    /// when the bond is established, the algorithm asked for by the listener
+    /// being used if any, or else the one asked for by the connecting side.
+    /// Both sides must be built with the feature of the algorithm settled on.
+    pub compression: Compression,
+    /// The key frames are encrypted with, using ChaCha20-Poly1305. Each
+    /// bond has its own session keys, derived from this key and from nonces
+    /// exchanged when the bond is established, and both sides must be
+    /// configured with the same key, or none at all.
+    pub psk: Option<PreSharedKey>,
+    /// The local addresses the substreams are bound to before connecting,
+    /// substream `i` leaving from address `i` modulo their number, e.g. to
+    /// send each one over a different uplink. A port of 0 lets the system
+    /// pick one. The system picks the source of every substream when empty,
+    /// and it is ignored on the accepting side.
+    pub local_addrs: Vec<std::net::SocketAddr>,
+    /// The socket options of the substreams, substream `i` being given the
+    /// options `i` modulo their number, e.g. to steer the substreams into
+    /// different QoS classes. They are set on both sides, by the connecting
+    /// one before connecting and by the accepting one once the bond is
+    /// complete, its listening sockets being given the largest receive
+    /// buffer beforehand for the TCP window scale to allow for it.
+    /// Substreams keep the system defaults when empty.
+    pub socket_options: Vec<SocketOptions>,
+    /// Notified of the events in the life of the bond, from the connection
+    /// of its substreams to its closure.
+    pub observer: Option<Observer>,
+    /// How long frames may wait on a substream that does not take any of
+    /// them before it is reported stalled to the observer, stalls being
+    /// detected within twice this time. Substreams are never reported
+    /// stalled if `None`, or with the io_uring backend.
+    pub stall_timeout: Option<std::time::Duration>,
+    /// Sends heartbeats on the substreams left idle, and keeps frames off a
+    /// substream that stays silent while the others are heard from, rather
+    /// than waiting on it until TCP gives up. Past the miss threshold the
+    /// substream is given up on, and the bond fails with
+    /// `BondError::Timeout` once none is left or the next frame to be
+    /// received was sent on one of them. They are negotiated when the bond
+    /// is established, both sides sending heartbeats if either asks, while
+    /// each side applies its own miss threshold. Frames are numbered along
+    /// with them.
+    pub heartbeat: Option<Heartbeat>,
+    /// Sends a ping on every substream at this interval, which the peer
+    /// echoes, to measure the round-trip time of each substream, reported by
+    /// `BondStream::stats`. Pings are negotiated when the bond is established,
+    /// each side answering those of the other if either asks, while only the
+    /// sides asking for them send them.
+    pub ping_interval: Option<std::time::Duration>,
+    /// How frames are spread over the substreams. Frames are numbered when
+    /// either side schedules them by latency, so that the receiver finds the
+    /// next one on whichever substream it was sent, while each side
+    /// schedules its own frames as configured.
+    pub scheduler: Scheduler,
+    /// The number of substreams every frame is sent on, the peer delivering
+    /// the first copy to arrive and discarding the others, which trades
+    /// bandwidth for a path that stalls never holding up the reader. Copies
+    /// go to the substreams a frame is expected to arrive first on once they
+    /// all answered a ping, or else to the next ones in turn, skipping those
+    /// without room for it. Frames are numbered when either side sets it, 1
+    /// sending every frame once while letting `BondStream::write_redundant`
+    /// send copies of some of them. It is capped by the number of
+    /// substreams, and 0 disables it.
+    pub redundancy: usize,
+    /// The registry the metrics of the bond, or of the bonds accepted by the
+    /// listener, are reported to.
+    #[cfg(feature = "metrics")]
+    pub metrics: Option<crate::metrics::Metrics>,
+}
+
+impl BondConfig {
+    /// Returns the socket options of substream `index`, if any.
+    pub(crate) fn socket_options_of(&self, index: usize) -> Option<&SocketOptions> {
+        match self.socket_options.len() {
+            0 => None,
+            n => Some(&self.socket_options[index % n]),
+        }
+    }
+
+    /// Reports the failed establishment of a bond to the metrics, if any.
+    pub(crate) fn handshake_failed(&self) {
+        #[cfg(feature = "metrics")]
+        if let Some(m) = self.metrics.as_ref() {
+            m.handshake_failed();
+        }
+    }
+
+    /// Reports to the metrics, if any, that a listener waits for the other
+    /// connections of `delta` more bonds, or fewer if negative.
+    #[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+    pub(crate) fn add_pending(&self, delta: i64) {
+        #[cfg(feature = "metrics")]
+        if let Some(m) = self.metrics.as_ref() {
+            m.add_pending(delta);
+        }
+    }
+}
+
+/// Socket options set on a substream, those left to `None` keeping the
+/// system default. The options must suit the transport of the bond, most of
+/// them only apply to TCP connections, and setting any of them fails the
+/// establishment of the bond.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SocketOptions {
+    /// The size of the send buffer, `SO_SNDBUF`.
+    pub send_buffer_size: Option<usize>,
+    /// The size of the receive buffer, `SO_RCVBUF`.
+    pub recv_buffer_size: Option<usize>,
+    /// The congestion control algorithm, `TCP_CONGESTION`, e.g. `cubic` or
+    /// `bbr`. Linux only.
+    pub congestion: Option<String>,
+    /// The amount of unsent data above which the socket is no longer
+    /// reported writable, `TCP_NOTSENT_LOWAT`. Linux only.
+    pub notsent_lowat: Option<u32>,
+    /// Enables TCP keepalive, `SO_KEEPALIVE`, with the given parameters.
+    pub keepalive: Option<Keepalive>,
+    /// The type of service of the packets sent, `IP_TOS` on IPv4 and
+    /// `IPV6_TCLASS` on IPv6, the DSCP being its upper six bits.
+    pub tos: Option<u32>,
+    /// The mark of the packets sent, `SO_MARK`, e.g. to select a routing
+    /// policy. Linux only, and requires `CAP_NET_ADMIN`.
+    pub mark: Option<u32>,
+}
+
+/// The heartbeats telling the substreams whose path went silent from those
+/// that are merely idle.
+///
+/// Heartbeats are control frames sent on the substreams nothing was sent on
+/// for the interval, whenever the bond is driven: all along by the thread of
+/// a bond in background mode, otherwise within `read` and `write`. A
+/// substream is only held silent while another one is heard from, so that a
+/// peer no longer driving its bond is not mistaken for a silent path, and
+/// bonds of a single substream are never failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Heartbeat {
+    /// The time after which an idle substream carries a heartbeat. Both
+    /// sides use the shorter interval of the two, in whole milliseconds.
+    pub interval: std::time::Duration,
+    /// The number of intervals a substream may stay silent while the others
+    /// are heard from before it is given up on, reported failed to the
+    /// observer, raised to 3 if lower. It is reported stalled after two
+    /// intervals already, frames being sent on the others from then on.
+    pub misses: u32,
+}
+
+impl Default for Heartbeat {
+    /// A heartbeat every second, the bond failing after three missed ones.
+    fn default() -> Heartbeat {
+        Heartbeat { interval: std::time::Duration::from_secs(1), misses: 3 }
+    }
+}
+
+/// The parameters of TCP keepalive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Keepalive {
+    /// The idle time before the first probe, `TCP_KEEPIDLE`.
+    pub time: std::time::Duration,
+    /// The time between probes, `TCP_KEEPINTVL`, the system default if `None`.
+    pub interval: Option<std::time::Duration>,
+    /// The number of unanswered probes before the connection is dropped,
+    /// `TCP_KEEPCNT`, the system default if `None`.
+    pub retries: Option<u32>,
+}
+
+/// The integrity check carried by every frame of a bond.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Checksum {
+    /// Frames carry no integrity check beyond the one of TCP.
+    #[default]
+    None,
+    /// Frames carry a CRC32C of their header and payload, checked before any
+    /// of the payload is handed to the application.
+    Crc32c,
+}
+
+/// The compression applied to the payload of every frame of a bond.
+///
+/// Fragments are compressed one at a time, those that do not get any smaller
+/// being sent as is, and a flag in the frame header tells which ones the
+/// receiver has to decompress.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compression {
+    /// Frames are sent as is.
+    #[default]
+    None,
+    /// Fragments are compressed with the LZ4 block format.
+    #[cfg(feature = "lz4")]
+    Lz4,
+    /// Fragments are compressed with zstd, at a level favouring speed.
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+/// A 256-bit key shared beforehand by both ends of a bond.
+#[derive(Clone, PartialEq, Eq)]
+pub struct PreSharedKey([u8; 32]);
+
+impl PreSharedKey {
+    /// Creates a pre-shared key out of its bytes.
+    pub fn new(key: [u8; 32]) -> PreSharedKey {
+        PreSharedKey(key)
+    }
+
+    pub(crate) fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for PreSharedKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("PreSharedKey(..)")
+    }
+}
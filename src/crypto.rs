@@ -0,0 +1,67 @@
+use std::io::IoSlice;
+
+use chacha20poly1305::aead::AeadInPlace;
+use chacha20poly1305::{ChaCha20Poly1305, KeyInit, Nonce, Tag};
+
+/// Size of the authentication tag preceding the ciphertext of every frame.
+pub(crate) const TAG_SIZE: usize = 16;
+pub(crate) const KEY_SIZE: usize = 32;
+
+/// The nonce space of the frames carrying payload, those of the control
+/// frames of each substream following it.
+pub(crate) const DATA_SPACE: u32 = 0;
+
+/// Returns the nonce of the frame with the given sequence number within
+/// `space`. Each direction of a bond has its own key, thus frames are
+/// numbered from zero in both.
+fn nonce(space: u32, seq: u64) -> Nonce {
+    let mut nonce = Nonce::default();
+    nonce[..4].copy_from_slice(&space.to_le_bytes());
+    nonce[4..].copy_from_slice(&seq.to_le_bytes());
+    nonce
+}
+
+/// Encrypts the payload of outgoing frames.
+pub(crate) struct Sealer {
+    aead: ChaCha20Poly1305,
+    buf: Vec<u8>,
+}
+
+impl Sealer {
+    pub(crate) fn new(key: &[u8; KEY_SIZE]) -> Sealer {
+        Sealer { aead: ChaCha20Poly1305::new(key.into()), buf: Vec::new() }
+    }
+
+    /// Encrypts the payload of frame `seq` of nonce space `space`,
+    /// authenticating the frame length `aad` along with it. Returns the tag
+    /// followed by the ciphertext.
+    pub(crate) fn seal(&mut self, space: u32, seq: u64, aad: &[u8], payload: &[IoSlice<'_>]) -> &[u8] {
+        self.buf.clear();
+        self.buf.resize(TAG_SIZE, 0);
+        payload.iter().for_each(|p| self.buf.extend_from_slice(p));
+        let (tag, text) = self.buf.split_at_mut(TAG_SIZE);
+        // Encryption only fails past 256 GiB in a single message.
+        let t = self.aead.encrypt_in_place_detached(&nonce(space, seq), aad, text).expect("frames are small enough to be encrypted");
+        tag.copy_from_slice(&t);
+        &self.buf
+    }
+}
+
+/// Decrypts the payload of incoming frames.
+pub(crate) struct Opener {
+    aead: ChaCha20Poly1305,
+}
+
+impl Opener {
+    pub(crate) fn new(key: &[u8; KEY_SIZE]) -> Opener {
+        Opener { aead: ChaCha20Poly1305::new(key.into()) }
+    }
+
+    /// Decrypts in place the payload of frame `seq` of nonce space `space`,
+    /// made of the tag followed by the ciphertext, returns `false` if it
+    /// fails authentication.
+    pub(crate) fn open(&self, space: u32, seq: u64, aad: &[u8], payload: &mut [u8]) -> bool {
+        let (tag, text) = payload.split_at_mut(TAG_SIZE);
+        self.aead.decrypt_in_place_detached(&nonce(space, seq), aad, text, Tag::from_slice(tag)).is_ok()
+    }
+}
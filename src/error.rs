@@ -0,0 +1,122 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+/// The failures specific to bonds.
+///
+/// Bonds implement `Read` and `Write`, so their failures come back as
+/// `std::io::Error`, with the kind documented on each variant. The
+/// `BondError` is kept as the inner error, to be recovered with
+/// `err.get_ref().and_then(|e| e.downcast_ref::<BondError>())`, or with
+/// `BondError::of`. Failures of the system calls made outside of the
+/// substreams, e.g. to open the poller, come back as they are.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum BondError {
+    /// The two sides could not settle the options of the bond, or the
+    /// number of its connections. `InvalidData`.
+    HandshakeMismatch {
+        /// What they disagree on.
+        reason: String,
+    },
+    /// One side encrypts the bond while the other does not, which is
+    /// `PermissionDenied`, or a frame failed authentication, most likely
+    /// as the sides hold different keys, which is `InvalidData`.
+    AuthenticationFailed {
+        /// The substream carrying the frame, `None` for the handshake.
+        substream: Option<usize>,
+        /// What failed.
+        reason: String,
+    },
+    /// I/O on a substream failed, failing the whole bond. The kind of the
+    /// underlying error.
+    SubstreamFailed {
+        /// The index of the substream in the bond.
+        index: usize,
+        /// The address of the peer of the substream, if it is an IP
+        /// connection.
+        peer: Option<SocketAddr>,
+        /// The failure of the substream.
+        error: Arc<std::io::Error>,
+    },
+    /// The peer sent something the protocol does not allow, e.g. a corrupted
+    /// frame or a connection joining a bond it does not belong to.
+    /// `InvalidData`.
+    ProtocolViolation {
+        /// The substream concerned, if any.
+        substream: Option<usize>,
+        /// What was sent.
+        reason: String,
+    },
+    /// The peer did not answer in time, or a substream stayed silent while
+    /// the others carried heartbeats, and either none is left or the next
+    /// frame was sent on it. `TimedOut`.
+    Timeout {
+        /// The substream concerned, if any.
+        substream: Option<usize>,
+        /// What was waited for.
+        reason: String,
+    },
+    /// The address to connect to could not be resolved. The kind of the
+    /// underlying error.
+    Resolution {
+        /// The failure of the resolution.
+        error: Arc<std::io::Error>,
+    },
+}
+
+impl BondError {
+    /// Returns the `BondError` carried by an I/O error, if any.
+    pub fn of(err: &std::io::Error) -> Option<&BondError> {
+        err.get_ref()?.downcast_ref()
+    }
+
+    /// Returns the kind of the I/O error the bond error converts to.
+    pub fn kind(&self) -> std::io::ErrorKind {
+        match self {
+            BondError::HandshakeMismatch { .. } | BondError::ProtocolViolation { .. } => std::io::ErrorKind::InvalidData,
+            BondError::AuthenticationFailed { substream: None, .. } => std::io::ErrorKind::PermissionDenied,
+            BondError::AuthenticationFailed { substream: Some(_), .. } => std::io::ErrorKind::InvalidData,
+            BondError::SubstreamFailed { error, .. } | BondError::Resolution { error } => error.kind(),
+            BondError::Timeout { .. } => std::io::ErrorKind::TimedOut,
+        }
+    }
+
+    /// Returns a protocol violation on the given substream.
+    pub(crate) fn protocol(substream: Option<usize>, reason: impl Into<String>) -> std::io::Error {
+        BondError::ProtocolViolation { substream, reason: reason.into() }.into()
+    }
+
+    /// Returns a mismatch of the options of both sides.
+    pub(crate) fn mismatch(reason: impl Into<String>) -> std::io::Error {
+        BondError::HandshakeMismatch { reason: reason.into() }.into()
+    }
+}
+
+impl std::fmt::Display for BondError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BondError::HandshakeMismatch { reason } => write!(f, "Handshake mismatch: {reason}"),
+            BondError::AuthenticationFailed { reason, .. } => f.write_str(reason),
+            BondError::SubstreamFailed { index, peer: Some(peer), error } => write!(f, "Substream {index} to {peer} failed: {error}"),
+            BondError::SubstreamFailed { index, peer: None, error } => write!(f, "Substream {index} failed: {error}"),
+            BondError::ProtocolViolation { reason, .. } => write!(f, "Protocol violation: {reason}"),
+            BondError::Timeout { reason, .. } => write!(f, "Timed out: {reason}"),
+            BondError::Resolution { error } => write!(f, "Cannot resolve the address to connect to: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for BondError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BondError::SubstreamFailed { error, .. } | BondError::Resolution { error } => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<BondError> for std::io::Error {
+    fn from(err: BondError) -> std::io::Error {
+        std::io::Error::new(err.kind(), err)
+    }
+}
@@ -0,0 +1,92 @@
+use std::net::SocketAddr;
+use std::os::fd::AsFd;
+use std::sync::Arc;
+
+use socket2::SockRef;
+use uuid::Uuid;
+
+/// Something that happened to a bond or to one of its substreams, reported
+/// to the `Observer` of its configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BondEvent {
+    /// What happened.
+    pub kind: BondEventKind,
+    /// The identifier the listener gave the bond, shared by both sides. Bonds
+    /// of a single connection and those made of connections established
+    /// beforehand have none.
+    pub cid: Option<Uuid>,
+    /// The index of the substream concerned, if any.
+    pub substream: Option<usize>,
+    /// The address of the peer of the substream concerned, if it is an IP
+    /// connection.
+    pub peer: Option<SocketAddr>,
+}
+
+/// The kinds of events in the life of a bond.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BondEventKind {
+    /// A connection was opened, or accepted, for a substream of the bond.
+    SubstreamConnected,
+    /// The options of the bond were settled with the peer.
+    HandshakeCompleted,
+    /// All the substreams are connected and the bond is ready for I/O.
+    BondFormed,
+    /// Frames have been waiting on a substream that could not take any of
+    /// them for longer than the stall timeout of the configuration, or
+    /// nothing was received on it for two heartbeat intervals while the
+    /// other substreams were heard from.
+    SubstreamStalled,
+    /// I/O on a substream failed, or a frame it carried was corrupted,
+    /// failing the whole bond. A substream silent past the miss threshold of
+    /// the heartbeats is reported failed as well, while the bond goes on
+    /// over the others.
+    SubstreamFailed {
+        /// The failure.
+        error: String,
+    },
+    /// The bond was dropped, closing its substreams.
+    BondClosed,
+}
+
+/// A callback notified of the events in the life of the bonds it is
+/// configured for.
+///
+/// The callback runs on the thread the event happens on, which is the
+/// background I/O thread for the events of a bond driven by one, and it
+/// should return promptly.
+#[derive(Clone)]
+pub struct Observer(Arc<dyn Fn(&BondEvent) + Send + Sync>);
+
+impl Observer {
+    /// Creates an observer calling `callback` on every event.
+    pub fn new(callback: impl Fn(&BondEvent) + Send + Sync + 'static) -> Observer {
+        Observer(Arc::new(callback))
+    }
+
+    /// Reports an event about a whole bond.
+    pub(crate) fn notify(&self, kind: BondEventKind, cid: Option<Uuid>) {
+        (self.0)(&BondEvent { kind, cid, substream: None, peer: None });
+    }
+
+    /// Reports an event about substream `index`, looking up its peer.
+    pub(crate) fn notify_substream(&self, kind: BondEventKind, cid: Option<Uuid>, index: usize, socket: &impl AsFd) {
+        self.notify_peer(kind, cid, index, peer_addr(socket));
+    }
+
+    /// Reports an event about substream `index`, whose peer is known.
+    pub(crate) fn notify_peer(&self, kind: BondEventKind, cid: Option<Uuid>, index: usize, peer: Option<SocketAddr>) {
+        (self.0)(&BondEvent { kind, cid, substream: Some(index), peer });
+    }
+}
+
+/// Returns the address of the peer of a socket, if it is an IP one.
+pub(crate) fn peer_addr(socket: &impl AsFd) -> Option<SocketAddr> {
+    SockRef::from(socket).peer_addr().ok()?.as_socket()
+}
+
+impl std::fmt::Debug for Observer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Observer(..)")
+    }
+}
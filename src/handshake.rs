@@ -0,0 +1,277 @@
+use std::io::{Read, Result as IoResult};
+use std::time::Duration;
+
+use hkdf::Hkdf;
+use sha2::Sha256;
+
+use crate::config::{BondConfig, Checksum, Compression, PreSharedKey, Scheduler};
+use crate::crypto::KEY_SIZE;
+use crate::error::BondError;
+
+/// Size of the random nonce each side contributes to the session keys.
+pub(crate) const NONCE_SIZE: usize = 32;
+
+const CHECKSUM_CRC32C: u8 = 0x01;
+/// The compression algorithm takes two bits, zero meaning no compression.
+const COMPRESSION_MASK: u8 = 0x06;
+#[cfg(feature = "lz4")]
+const COMPRESSION_LZ4: u8 = 0x02;
+#[cfg(feature = "zstd")]
+const COMPRESSION_ZSTD: u8 = 0x04;
+const ENCRYPTED: u8 = 0x08;
+const HEARTBEAT: u8 = 0x10;
+const PINGS: u8 = 0x20;
+const NUMBERED: u8 = 0x40;
+
+/// The options a bond operates with.
+///
+/// The connecting side proposes its options right after the identifier of
+/// its first connection, and the listener answers with the options settled
+/// for the bond along with the number of substreams. Options are encoded as
+/// a single byte of flags, unknown flags being rejected by both sides.
+///
+/// When a side asks for heartbeats, it follows its options with the interval
+/// it asks for, in milliseconds as a little-endian `u32`, and the listener
+/// answers with the shorter of both. When frames are encrypted, each side
+/// then follows with a random nonce, the keys of the session being derived
+/// from both nonces and the pre-shared key, and bound to all that was
+/// proposed and answered, so that the sides do not agree on the keys if the
+/// options were tampered with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct Options {
+    pub(crate) checksum: Checksum,
+    pub(crate) compression: Compression,
+    pub(crate) encrypted: bool,
+    pub(crate) heartbeat: bool,
+    /// Whether pings may be sent, each side answering those of the other.
+    pub(crate) pings: bool,
+    /// Whether frames carry their sequence number, which lets them be
+    /// scheduled on any substream rather than round-robin, sent on several
+    /// of them, and kept off the substreams that went silent.
+    pub(crate) numbered: bool,
+}
+
+impl Options {
+    /// Returns the options asked for by the given configuration.
+    pub(crate) fn requested(config: &BondConfig) -> Options {
+        Options {
+            checksum: config.checksum,
+            compression: config.compression,
+            encrypted: config.psk.is_some(),
+            heartbeat: config.heartbeat.is_some(),
+            pings: config.ping_interval.is_some() || config.scheduler == Scheduler::Latency,
+            numbered: config.scheduler == Scheduler::Latency || config.redundancy > 0 || config.heartbeat.is_some(),
+        }
+    }
+
+    /// Settles the options proposed by the peer against the local ones, a
+    /// feature being used if either side asks for it. When both sides ask
+    /// for a different compression, the local one prevails. Encryption is
+    /// the exception, both sides have to ask for it.
+    pub(crate) fn settle(self, peer: Options) -> IoResult<Options> {
+        if self.encrypted != peer.encrypted {
+            return Err(encryption_mismatch(self.encrypted));
+        }
+        let checksum = match (self.checksum, peer.checksum) {
+            (Checksum::None, Checksum::None) => Checksum::None,
+            _ => Checksum::Crc32c,
+        };
+        let compression = if self.compression == Compression::None { peer.compression } else { self.compression };
+        Ok(Options {
+            checksum,
+            compression,
+            encrypted: self.encrypted,
+            heartbeat: self.heartbeat || peer.heartbeat,
+            pings: self.pings || peer.pings,
+            numbered: self.numbered || peer.numbered,
+        })
+    }
+
+    pub(crate) fn to_byte(self) -> u8 {
+        let checksum = match self.checksum {
+            Checksum::None => 0,
+            Checksum::Crc32c => CHECKSUM_CRC32C,
+        };
+        let compression = match self.compression {
+            Compression::None => 0,
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => COMPRESSION_LZ4,
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => COMPRESSION_ZSTD,
+        };
+        let encrypted = if self.encrypted { ENCRYPTED } else { 0 };
+        let heartbeat = if self.heartbeat { HEARTBEAT } else { 0 };
+        let pings = if self.pings { PINGS } else { 0 };
+        let numbered = if self.numbered { NUMBERED } else { 0 };
+        checksum | compression | encrypted | heartbeat | pings | numbered
+    }
+
+    pub(crate) fn from_byte(flags: u8) -> IoResult<Options> {
+        if flags & !(CHECKSUM_CRC32C | COMPRESSION_MASK | ENCRYPTED | HEARTBEAT | PINGS | NUMBERED) != 0 {
+            return Err(BondError::mismatch(format!("Unknown bond options {flags:#04x}")));
+        }
+        let checksum = if flags & CHECKSUM_CRC32C != 0 { Checksum::Crc32c } else { Checksum::None };
+        let compression = match flags & COMPRESSION_MASK {
+            0 => Compression::None,
+            #[cfg(feature = "lz4")]
+            COMPRESSION_LZ4 => Compression::Lz4,
+            #[cfg(feature = "zstd")]
+            COMPRESSION_ZSTD => Compression::Zstd,
+            code => {
+                return Err(BondError::mismatch(format!("Unsupported bond compression {code:#04x}")));
+            }
+        };
+        Ok(Options {
+            checksum,
+            compression,
+            encrypted: flags & ENCRYPTED != 0,
+            heartbeat: flags & HEARTBEAT != 0,
+            pings: flags & PINGS != 0,
+            numbered: flags & NUMBERED != 0,
+        })
+    }
+}
+
+/// What a bond is established with: the options settled by both sides,
+/// the keys of the session when frames are encrypted and the interval of
+/// the heartbeats when they are sent.
+pub(crate) struct Session {
+    pub(crate) options: Options,
+    pub(crate) keys: Option<SessionKeys>,
+    pub(crate) heartbeat: Option<Duration>,
+}
+
+/// Returns the error of a side expecting encryption, or not, while its peer
+/// does otherwise.
+fn encryption_mismatch(encrypted: bool) -> std::io::Error {
+    let msg = if encrypted { "The peer does not encrypt the bond" } else { "The peer encrypts the bond, yet no pre-shared key is configured" };
+    BondError::AuthenticationFailed { substream: None, reason: msg.into() }.into()
+}
+
+/// Returns what the connecting side proposes: its options, followed by the
+/// interval of its heartbeats and by its nonce when frames are to be
+/// encrypted, along with the nonce.
+pub(crate) fn propose(config: &BondConfig) -> IoResult<(Vec<u8>, Option<[u8; NONCE_SIZE]>)> {
+    let mut proposal = vec![Options::requested(config).to_byte()];
+    if let Some(heartbeat) = config.heartbeat {
+        proposal.extend_from_slice(&interval_ms(heartbeat.interval).to_le_bytes());
+    }
+    let nonce = match config.psk {
+        Some(_) => Some(nonce()?),
+        None => None,
+    };
+    if let Some(nonce) = nonce.as_ref() {
+        proposal.extend_from_slice(nonce);
+    }
+    Ok((proposal, nonce))
+}
+
+/// Settles the options proposed by the connecting side as `flags`, reading
+/// the rest of its proposal from `peer`. Returns the answer, made of the
+/// settled options followed by the interval of the heartbeats and by the
+/// local nonce when frames are encrypted, along with the session.
+pub(crate) fn answer(config: &BondConfig, flags: u8, peer: &mut impl Read) -> IoResult<(Vec<u8>, Session)> {
+    let proposed = Options::from_byte(flags)?;
+    let options = Options::requested(config).settle(proposed)?;
+    let mut proposal = vec![flags];
+    let mut answer = vec![options.to_byte()];
+    let peer_interval = if proposed.heartbeat { Some(read_interval(peer)?) } else { None };
+    if let Some(interval) = peer_interval {
+        proposal.extend_from_slice(&interval.to_le_bytes());
+    }
+    let heartbeat = match (config.heartbeat.map(|h| interval_ms(h.interval)), peer_interval) {
+        (Some(local), Some(peer)) => Some(local.min(peer)),
+        (local, peer) => local.or(peer),
+    };
+    if let Some(interval) = heartbeat {
+        answer.extend_from_slice(&interval.to_le_bytes());
+    }
+    // Each side contributes a nonce to the keys of an encrypted session.
+    let keys = match config.psk.as_ref() {
+        Some(psk) => {
+            let mut peer_nonce = [0u8; NONCE_SIZE];
+            peer.read_exact(&mut peer_nonce)?;
+            proposal.extend_from_slice(&peer_nonce);
+            let nonce = nonce()?;
+            answer.extend_from_slice(&nonce);
+            Some(SessionKeys::derive(psk, &peer_nonce, &nonce, &[proposal, answer.clone()].concat(), false))
+        }
+        None => None,
+    };
+    Ok((answer, Session { options, keys, heartbeat: heartbeat.map(|ms| Duration::from_millis(ms.into())) }))
+}
+
+/// Completes the session of the connecting side, which proposed
+/// `proposal`, out of the options settled by the listener as `flags`,
+/// reading the rest of its answer from `peer`.
+pub(crate) fn conclude(config: &BondConfig, proposal: &[u8], nonce: Option<[u8; NONCE_SIZE]>, flags: u8, peer: &mut impl Read) -> IoResult<Session> {
+    let options = Options::from_byte(flags)?;
+    if options.encrypted != config.psk.is_some() {
+        return Err(encryption_mismatch(config.psk.is_some()));
+    }
+    let mut answer = vec![flags];
+    let interval = if options.heartbeat { Some(read_interval(peer)?) } else { None };
+    if let Some(interval) = interval {
+        answer.extend_from_slice(&interval.to_le_bytes());
+    }
+    let keys = match (config.psk.as_ref(), nonce) {
+        (Some(psk), Some(nonce)) => {
+            let mut listener_nonce = [0u8; NONCE_SIZE];
+            peer.read_exact(&mut listener_nonce)?;
+            answer.extend_from_slice(&listener_nonce);
+            Some(SessionKeys::derive(psk, &nonce, &listener_nonce, &[proposal, &answer].concat(), true))
+        }
+        _ => None,
+    };
+    Ok(Session { options, keys, heartbeat: interval.map(|ms| Duration::from_millis(ms.into())) })
+}
+
+/// Returns a heartbeat interval as sent in the handshake.
+fn interval_ms(interval: Duration) -> u32 {
+    interval.as_millis().clamp(1, u32::MAX.into()) as u32
+}
+
+/// Reads a heartbeat interval sent in the handshake.
+fn read_interval(peer: &mut impl Read) -> IoResult<u32> {
+    let mut ms = [0u8; size_of::<u32>()];
+    peer.read_exact(&mut ms)?;
+    match u32::from_le_bytes(ms) {
+        0 => Err(BondError::protocol(None, "Heartbeats asked for with an interval of 0")),
+        ms => Ok(ms),
+    }
+}
+
+/// Returns a fresh random nonce.
+fn nonce() -> IoResult<[u8; NONCE_SIZE]> {
+    let mut nonce = [0u8; NONCE_SIZE];
+    getrandom::fill(&mut nonce)?;
+    Ok(nonce)
+}
+
+/// The keys encrypting each direction of a bond.
+pub(crate) struct SessionKeys {
+    pub(crate) tx: [u8; KEY_SIZE],
+    pub(crate) rx: [u8; KEY_SIZE],
+}
+
+impl SessionKeys {
+    /// Derives the keys of a session from the pre-shared key and the nonces
+    /// of the connecting and accepting sides, binding them to `transcript`,
+    /// the proposal of the connecting side followed by the answer of the
+    /// listener, from their options to their nonces. `connector` tells which
+    /// side the keys are for.
+    pub(crate) fn derive(psk: &PreSharedKey, connector_nonce: &[u8; NONCE_SIZE], listener_nonce: &[u8; NONCE_SIZE], transcript: &[u8], connector: bool) -> SessionKeys {
+        let salt = [connector_nonce.as_slice(), listener_nonce].concat();
+        let hkdf = Hkdf::<Sha256>::new(Some(&salt), psk.as_bytes());
+        let mut upstream = [0u8; KEY_SIZE];
+        let mut downstream = [0u8; KEY_SIZE];
+        // The output is far below the limit of 255 hashes.
+        hkdf.expand_multi_info(&[b"bond-tcp connector to listener", transcript], &mut upstream).expect("valid key length");
+        hkdf.expand_multi_info(&[b"bond-tcp listener to connector", transcript], &mut downstream).expect("valid key length");
+        if connector {
+            SessionKeys { tx: upstream, rx: downstream }
+        } else {
+            SessionKeys { tx: downstream, rx: upstream }
+        }
+    }
+}
@@ -30,7 +30,7 @@
 //! ### Server Side
 //!
 //! ```rust,no_run
-//! use bnd_socket::BondTcpListener;
+//! use bond_tcp::BondTcpListener;
 //! use std::io::{Read, Write};
 //!
 //! // Create a listener that bonds 3 connections per client
@@ -104,5 +104,32 @@
 
 #![warn(missing_docs)]
 
+mod background;
+mod bond;
 mod bond_tcp;
+mod bond_unix;
+mod compress;
+mod config;
+mod crypto;
+mod error;
+mod events;
+mod handshake;
+mod link;
+#[cfg(feature = "metrics")]
+mod metrics;
+mod sockopt;
+mod stats;
+mod trace;
+mod transport;
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+mod uring;
+pub use bond::IoCounters;
 pub use bond_tcp::*;
+pub use bond_unix::*;
+pub use config::*;
+pub use error::BondError;
+pub use events::*;
+#[cfg(feature = "metrics")]
+pub use metrics::Metrics;
+pub use stats::*;
+pub use transport::Transport;
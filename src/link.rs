@@ -0,0 +1,140 @@
+use std::io::{IoSlice, Read, Result as IoResult, Write};
+
+use crate::transport::Transport;
+
+/// One of the connections making up a bond: a transport, possibly secured by
+/// TLS.
+///
+/// The same reads and writes serve to establish the bond, while the socket
+/// blocks, and then for the bond to drive the substream, once the socket no
+/// longer blocks. TLS records are only pulled from the socket once the
+/// plaintext received so far has been read, and the records of a write are
+/// written to the socket before the next write is taken, so that at most one
+/// write worth of records is left pending when the socket would block.
+pub(crate) struct Link<T> {
+    pub(crate) stream: T,
+    #[cfg(feature = "rustls")]
+    tls: Option<Box<rustls::Connection>>,
+}
+
+impl<T: Transport> Link<T> {
+    pub(crate) fn plain(stream: T) -> Link<T> {
+        Link {
+            stream,
+            #[cfg(feature = "rustls")]
+            tls: None,
+        }
+    }
+
+    /// Secures the given transport, blocking until the TLS handshake
+    /// completes.
+    #[cfg(feature = "rustls")]
+    pub(crate) fn tls(mut stream: T, conn: impl Into<rustls::Connection>) -> IoResult<Link<T>> {
+        let mut conn = Box::new(conn.into());
+        while conn.is_handshaking() {
+            conn.complete_io(&mut stream)?;
+        }
+        tracing::debug!(kind = ?conn.handshake_kind(), "TLS handshake completed");
+        Ok(Link { stream, tls: Some(conn) })
+    }
+
+    pub(crate) fn is_tls(&self) -> bool {
+        #[cfg(feature = "rustls")]
+        if self.tls.is_some() {
+            return true;
+        }
+        false
+    }
+
+    /// Writes the TLS records left pending by previous writes, returns
+    /// whether none are left.
+    pub(crate) fn flush_records(&mut self) -> IoResult<bool> {
+        #[cfg(feature = "rustls")]
+        if let Some(conn) = self.tls.as_mut() {
+            return write_records(conn, &mut self.stream);
+        }
+        Ok(true)
+    }
+
+    /// Returns whether TLS records are pending.
+    pub(crate) fn has_records(&self) -> bool {
+        #[cfg(feature = "rustls")]
+        if let Some(conn) = self.tls.as_ref() {
+            return conn.wants_write();
+        }
+        false
+    }
+
+    /// Tells the peer that the TLS session is being closed, as long as the
+    /// socket takes the alert without blocking.
+    pub(crate) fn close(&mut self) {
+        #[cfg(feature = "rustls")]
+        if let Some(conn) = self.tls.as_mut() {
+            conn.send_close_notify();
+            let _ = self.flush_records();
+        }
+    }
+}
+
+impl<T: Transport> Read for Link<T> {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        #[cfg(feature = "rustls")]
+        if let Some(conn) = self.tls.as_mut() {
+            loop {
+                match conn.reader().read(buf) {
+                    Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+                    res => return res,
+                }
+                conn.read_tls(&mut self.stream)?;
+                conn.process_new_packets().map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+                // Messages such as key updates may call for an answer.
+                write_records(conn, &mut self.stream)?;
+            }
+        }
+        self.stream.read(buf)
+    }
+}
+
+impl<T: Transport> Write for Link<T> {
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        self.write_vectored(&[IoSlice::new(buf)])
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> IoResult<usize> {
+        #[cfg(feature = "rustls")]
+        if let Some(conn) = self.tls.as_mut() {
+            if !write_records(conn, &mut self.stream)? {
+                return Err(std::io::ErrorKind::WouldBlock.into());
+            }
+            let n = conn.writer().write_vectored(bufs)?;
+            if n == 0 && bufs.iter().any(|b| !b.is_empty()) {
+                return Err(std::io::ErrorKind::WouldBlock.into());
+            }
+            write_records(conn, &mut self.stream)?;
+            return Ok(n);
+        }
+        self.stream.write_vectored(bufs)
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        if !self.flush_records()? {
+            return Err(std::io::ErrorKind::WouldBlock.into());
+        }
+        self.stream.flush()
+    }
+}
+
+/// Writes the pending records of a TLS connection to the socket, returns
+/// whether none are left.
+#[cfg(feature = "rustls")]
+fn write_records(conn: &mut rustls::Connection, stream: &mut impl Write) -> IoResult<bool> {
+    while conn.wants_write() {
+        match conn.write_tls(stream) {
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => return Ok(false),
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
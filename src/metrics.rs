@@ -0,0 +1,143 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError, Weak};
+
+use uuid::Uuid;
+
+/// A registry of metrics about the bonds and listeners it is configured
+/// for, rendered in the Prometheus text exposition format.
+///
+/// The same registry is meant to be shared by every bond and listener of a
+/// process, through `BondConfig::metrics`. The traffic of a substream is
+/// counted as its frames are sent and received, the other metrics as the
+/// bonds are established, fail and close, and rendering them only reads
+/// atomic counters.
+///
+/// ```rust,no_run
+/// use bond_tcp::{BondConfig, BondTcpListener, Metrics};
+///
+/// let metrics = Metrics::new();
+/// let config = BondConfig { metrics: Some(metrics.clone()), ..Default::default() };
+/// let mut listener = BondTcpListener::bind_with("127.0.0.1:8080", 3, config)?;
+/// let (stream, _) = listener.accept()?;
+/// print!("{}", metrics.render());
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Clone, Default)]
+pub struct Metrics(Arc<Registry>);
+
+#[derive(Default)]
+struct Registry {
+    bonds_formed: AtomicU64,
+    pending: AtomicI64,
+    handshake_failures: AtomicU64,
+    substream_failures: AtomicU64,
+    /// The bonds still open, those dropped being pruned when rendering.
+    bonds: Mutex<Vec<Weak<BondMetrics>>>,
+}
+
+/// The metrics of one bond, updated by the bond itself.
+pub(crate) struct BondMetrics {
+    /// The label of the bond: its identifier, or for bonds without one a
+    /// number unique to the registry.
+    label: String,
+    /// The bytes received on the substreams other than the one carrying the
+    /// next frame, waiting for their turn to be handed out.
+    pub(crate) reorder_depth: AtomicU64,
+    pub(crate) substreams: Vec<Arc<SubstreamMetrics>>,
+    registry: Metrics,
+}
+
+/// The traffic of one substream, headers included.
+#[derive(Default)]
+pub(crate) struct SubstreamMetrics {
+    pub(crate) bytes_sent: AtomicU64,
+    pub(crate) bytes_received: AtomicU64,
+}
+
+impl Metrics {
+    /// Creates an empty registry.
+    pub fn new() -> Metrics {
+        Metrics::default()
+    }
+
+    /// Registers a bond of `substreams` substreams that was just formed.
+    pub(crate) fn bond_formed(&self, cid: Option<Uuid>, substreams: usize) -> Arc<BondMetrics> {
+        let n = self.0.bonds_formed.fetch_add(1, Ordering::Relaxed);
+        let bond = Arc::new(BondMetrics {
+            label: cid.map_or_else(|| n.to_string(), |cid| cid.to_string()),
+            reorder_depth: AtomicU64::new(0),
+            substreams: (0..substreams).map(|_| Arc::default()).collect(),
+            registry: self.clone(),
+        });
+        self.0.bonds.lock().unwrap_or_else(PoisonError::into_inner).push(Arc::downgrade(&bond));
+        bond
+    }
+
+    /// Records that a listener now waits for the other connections of
+    /// `delta` more bonds, or fewer if negative.
+    pub(crate) fn add_pending(&self, delta: i64) {
+        self.0.pending.fetch_add(delta, Ordering::Relaxed);
+    }
+
+    /// Records that the establishment of a bond failed.
+    pub(crate) fn handshake_failed(&self) {
+        self.0.handshake_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Renders the metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let bonds: Vec<Arc<BondMetrics>> = {
+            let mut bonds = self.0.bonds.lock().unwrap_or_else(PoisonError::into_inner);
+            bonds.retain(|b| b.strong_count() > 0);
+            bonds.iter().filter_map(Weak::upgrade).collect()
+        };
+        let mut out = String::new();
+        metric(&mut out, "bonds_active", "gauge", "Bonds currently open.");
+        let _ = writeln!(out, "bond_tcp_bonds_active {}", bonds.len());
+        metric(&mut out, "bonds_formed_total", "counter", "Bonds formed since the registry was created.");
+        let _ = writeln!(out, "bond_tcp_bonds_formed_total {}", self.0.bonds_formed.load(Ordering::Relaxed));
+        metric(&mut out, "bonds_pending", "gauge", "Bonds accepted by a listener and waiting for their other connections.");
+        let _ = writeln!(out, "bond_tcp_bonds_pending {}", self.0.pending.load(Ordering::Relaxed));
+        metric(&mut out, "handshake_failures_total", "counter", "Bonds whose establishment failed.");
+        let _ = writeln!(out, "bond_tcp_handshake_failures_total {}", self.0.handshake_failures.load(Ordering::Relaxed));
+        metric(&mut out, "substream_failures_total", "counter", "Substreams whose I/O failed or that carried a corrupted frame.");
+        let _ = writeln!(out, "bond_tcp_substream_failures_total {}", self.0.substream_failures.load(Ordering::Relaxed));
+        metric(&mut out, "reorder_buffer_bytes", "gauge", "Bytes received ahead of the frame to be handed out next.");
+        for b in bonds.iter() {
+            let _ = writeln!(out, "bond_tcp_reorder_buffer_bytes{{bond=\"{}\"}} {}", b.label, b.reorder_depth.load(Ordering::Relaxed));
+        }
+        metric(&mut out, "substream_sent_bytes_total", "counter", "Bytes of the frames sent on a substream.");
+        for b in bonds.iter() {
+            for (i, s) in b.substreams.iter().enumerate() {
+                let _ = writeln!(out, "bond_tcp_substream_sent_bytes_total{{bond=\"{}\",substream=\"{i}\"}} {}", b.label, s.bytes_sent.load(Ordering::Relaxed));
+            }
+        }
+        metric(&mut out, "substream_received_bytes_total", "counter", "Bytes of the frames received on a substream.");
+        for b in bonds.iter() {
+            for (i, s) in b.substreams.iter().enumerate() {
+                let _ = writeln!(out, "bond_tcp_substream_received_bytes_total{{bond=\"{}\",substream=\"{i}\"}} {}", b.label, s.bytes_received.load(Ordering::Relaxed));
+            }
+        }
+        out
+    }
+}
+
+impl BondMetrics {
+    /// Records the failure of a substream of the bond.
+    pub(crate) fn substream_failed(&self) {
+        self.registry.0.substream_failures.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Writes the help and type lines of a metric.
+fn metric(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP bond_tcp_{name} {help}");
+    let _ = writeln!(out, "# TYPE bond_tcp_{name} {kind}");
+}
+
+impl std::fmt::Debug for Metrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Metrics(..)")
+    }
+}
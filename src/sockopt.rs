@@ -0,0 +1,91 @@
+use std::io::Result as IoResult;
+use std::os::fd::AsFd;
+
+use socket2::{SockRef, TcpKeepalive};
+
+use crate::config::{BondConfig, SocketOptions};
+use crate::link::Link;
+
+/// Sets the TTL of a socket, which is its unicast hop limit when it is an
+/// IPv6 one.
+pub(crate) fn set_ttl(socket: &impl AsFd, ttl: u32) -> IoResult<()> {
+    let socket = SockRef::from(socket);
+    match socket.local_addr()?.is_ipv6() {
+        true => socket.set_unicast_hops_v6(ttl),
+        false => socket.set_ttl_v4(ttl),
+    }
+}
+
+/// Gets the TTL of a socket, or its unicast hop limit.
+pub(crate) fn ttl(socket: &impl AsFd) -> IoResult<u32> {
+    let socket = SockRef::from(socket);
+    match socket.local_addr()?.is_ipv6() {
+        true => socket.unicast_hops_v6(),
+        false => socket.ttl_v4(),
+    }
+}
+
+/// Sets the socket options the configuration gives to every substream, the
+/// links being in the order of the bond.
+pub(crate) fn configure<T: AsFd>(links: &[Link<T>], config: &BondConfig) -> IoResult<()> {
+    for (index, link) in links.iter().enumerate() {
+        if let Some(options) = config.socket_options_of(index) {
+            apply(&link.stream, options)?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns the largest receive buffer the configuration gives to a
+/// substream, which listening sockets are given for the connections they
+/// accept.
+pub(crate) fn largest_recv_buffer(config: &BondConfig) -> Option<usize> {
+    config.socket_options.iter().filter_map(|o| o.recv_buffer_size).max()
+}
+
+/// Sets the given options on a socket.
+pub(crate) fn apply(socket: &impl AsFd, options: &SocketOptions) -> IoResult<()> {
+    let socket = SockRef::from(socket);
+    if let Some(size) = options.send_buffer_size {
+        socket.set_send_buffer_size(size)?;
+    }
+    if let Some(size) = options.recv_buffer_size {
+        socket.set_recv_buffer_size(size)?;
+    }
+    if let Some(keepalive) = options.keepalive {
+        let mut params = TcpKeepalive::new().with_time(keepalive.time);
+        if let Some(interval) = keepalive.interval {
+            params = params.with_interval(interval);
+        }
+        if let Some(retries) = keepalive.retries {
+            params = params.with_retries(retries);
+        }
+        socket.set_tcp_keepalive(&params)?;
+    }
+    if let Some(tos) = options.tos {
+        match socket.local_addr()?.is_ipv6() {
+            true => socket.set_tclass_v6(tos)?,
+            false => socket.set_tos_v4(tos)?,
+        }
+    }
+    #[cfg(target_os = "linux")]
+    {
+        if let Some(congestion) = &options.congestion {
+            socket.set_tcp_congestion(congestion.as_bytes())?;
+        }
+        if let Some(lowat) = options.notsent_lowat {
+            socket.set_tcp_notsent_lowat(lowat)?;
+        }
+        if let Some(mark) = options.mark {
+            socket.set_mark(mark)?;
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    if options.congestion.is_some() || options.notsent_lowat.is_some() || options.mark.is_some() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "The congestion control, the low watermark and the mark are only set on Linux",
+        ));
+    }
+    Ok(())
+}
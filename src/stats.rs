@@ -0,0 +1,180 @@
+use std::os::fd::AsFd;
+use std::time::Duration;
+
+use crate::bond::IoCounters;
+
+/// The traffic of a bond and of each of its substreams, as returned by
+/// `BondStream::stats`.
+///
+/// The counters are copied out of the bond and the TCP state is queried with
+/// one system call per substream, so stats are cheap enough to be sampled
+/// periodically, e.g. every second, the rates being derived from the
+/// differences between samples.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BondStats {
+    /// The system calls issued and the bytes moved by the bond.
+    pub io: IoCounters,
+    /// The time the bond spent blocked waiting for its substreams to be
+    /// ready, or for the application when driven by a background thread.
+    pub wait_time: Duration,
+    /// The stats of every substream, in the order of the bond.
+    pub substreams: Vec<SubstreamStats>,
+}
+
+impl BondStats {
+    /// Returns the traffic of all the substreams added up.
+    pub fn totals(&self) -> SubstreamCounters {
+        self.substreams.iter().fold(SubstreamCounters::default(), |total, s| SubstreamCounters {
+            frames_sent: total.frames_sent + s.counters.frames_sent,
+            frames_received: total.frames_received + s.counters.frames_received,
+            bytes_sent: total.bytes_sent + s.counters.bytes_sent,
+            bytes_received: total.bytes_received + s.counters.bytes_received,
+            duplicates_received: total.duplicates_received + s.counters.duplicates_received,
+        })
+    }
+}
+
+/// The stats of one substream of a bond.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SubstreamStats {
+    /// The frames carried by the substream.
+    pub counters: SubstreamCounters,
+    /// The state of the TCP connection, `None` if the substream is not one
+    /// or the system does not report it.
+    pub tcp: Option<TcpInfo>,
+    /// The round-trip time measured by the bond, `None` until a ping sent
+    /// on the substream was answered.
+    pub rtt: Option<RttEstimate>,
+}
+
+/// The frames carried by one substream, each frame carrying one fragment of
+/// the payload.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SubstreamCounters {
+    /// Number of frames handed to the substream for transmission.
+    pub frames_sent: u64,
+    /// Number of frames received on the substream.
+    pub frames_received: u64,
+    /// Number of bytes of the frames handed to the substream, headers
+    /// included and payloads as carried, possibly compressed.
+    pub bytes_sent: u64,
+    /// Number of bytes of the frames received on the substream.
+    pub bytes_received: u64,
+    /// Number of the frames received on the substream that were copies of
+    /// frames received first on another one, which are discarded.
+    pub duplicates_received: u64,
+}
+
+/// The round-trip time of a substream, measured by the bond with the pings
+/// it sends every `BondConfig::ping_interval`, each echoed by the peer.
+///
+/// Unlike the one measured by TCP, it spans the whole way of a frame from one
+/// bond to the other: the frames scheduled on the substream ahead of the
+/// ping, the send buffer of the socket, and the time until the peer gets to
+/// the ping and answers it, which is only short while the peer drives its
+/// bond, e.g. in background mode or while it reads.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RttEstimate {
+    /// The smoothed round-trip time, each sample weighing for 1/8 as with
+    /// TCP (RFC 6298).
+    pub smoothed: Duration,
+    /// The jitter, the smoothed deviation of the samples from the smoothed
+    /// round-trip time, each sample weighing for 1/4.
+    pub jitter: Duration,
+    /// The last sample.
+    pub latest: Duration,
+    /// The lowest sample.
+    pub min: Duration,
+    /// The number of samples taken.
+    pub samples: u64,
+}
+
+impl RttEstimate {
+    /// Accounts for a new sample.
+    pub(crate) fn update(&mut self, sample: Duration) {
+        if self.samples == 0 {
+            self.smoothed = sample;
+            self.jitter = sample / 2;
+            self.min = sample;
+        } else {
+            self.jitter = (self.jitter * 3 + self.smoothed.abs_diff(sample)) / 4;
+            self.smoothed = (self.smoothed * 7 + sample) / 8;
+            self.min = self.min.min(sample);
+        }
+        self.latest = sample;
+        self.samples += 1;
+    }
+
+    /// Returns the estimate, `None` until the first sample.
+    pub(crate) fn get(&self) -> Option<RttEstimate> {
+        (self.samples > 0).then_some(*self)
+    }
+}
+
+/// The state of a TCP connection as reported by `TCP_INFO` on Linux.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TcpInfo {
+    /// The smoothed round-trip time.
+    pub rtt: Duration,
+    /// The variation of the round-trip time.
+    pub rtt_var: Duration,
+    /// The lowest round-trip time observed.
+    pub min_rtt: Duration,
+    /// The congestion window, in segments.
+    pub cwnd: u32,
+    /// The maximum segment size of the sender.
+    pub mss: u32,
+    /// The total number of segments retransmitted.
+    pub retransmits: u32,
+    /// The number of segments currently considered lost.
+    pub lost: u32,
+    /// The total number of bytes retransmitted.
+    pub bytes_retransmitted: u64,
+    /// The bytes written but not yet sent.
+    pub notsent_bytes: u32,
+    /// The most recent delivery rate, in bytes per second.
+    pub delivery_rate: u64,
+    /// The current pacing rate, in bytes per second.
+    pub pacing_rate: u64,
+}
+
+/// Queries the state of a TCP connection, `None` if the socket is not one.
+#[cfg(target_os = "linux")]
+pub(crate) fn tcp_info(socket: &impl AsFd) -> Option<TcpInfo> {
+    use std::os::fd::AsRawFd;
+
+    // SAFETY: `tcp_info` is plain old data, which the kernel fills up to
+    // the length it supports.
+    let mut info: libc::tcp_info = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of::<libc::tcp_info>() as libc::socklen_t;
+    let res = unsafe {
+        libc::getsockopt(
+            socket.as_fd().as_raw_fd(),
+            libc::IPPROTO_TCP,
+            libc::TCP_INFO,
+            (&mut info as *mut libc::tcp_info).cast(),
+            &mut len,
+        )
+    };
+    if res != 0 {
+        return None;
+    }
+    Some(TcpInfo {
+        rtt: Duration::from_micros(info.tcpi_rtt.into()),
+        rtt_var: Duration::from_micros(info.tcpi_rttvar.into()),
+        min_rtt: Duration::from_micros(info.tcpi_min_rtt.into()),
+        cwnd: info.tcpi_snd_cwnd,
+        mss: info.tcpi_snd_mss,
+        retransmits: info.tcpi_total_retrans,
+        lost: info.tcpi_lost,
+        bytes_retransmitted: info.tcpi_bytes_retrans,
+        notsent_bytes: info.tcpi_notsent_bytes,
+        delivery_rate: info.tcpi_delivery_rate,
+        pacing_rate: info.tcpi_pacing_rate,
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn tcp_info(_socket: &impl AsFd) -> Option<TcpInfo> {
+    None
+}
@@ -0,0 +1,26 @@
+use tracing::{field, Span};
+use uuid::Uuid;
+
+/// Emits a trace event on the I/O path, i.e. once per frame or system call.
+/// Such events are compiled out unless the `trace-io` feature is enabled, as
+/// even filtered out they would cost throughput.
+macro_rules! io_trace {
+    ($($arg:tt)+) => {
+        #[cfg(feature = "trace-io")]
+        {
+            tracing::trace!($($arg)+);
+        }
+    };
+}
+pub(crate) use io_trace;
+
+/// Creates the span of a bond, under which everything happening to the bond
+/// and to its substreams is recorded, from its handshake to its closure. The
+/// identifier and the peer of the bond are recorded once known.
+pub(crate) fn bond_span(cid: Option<Uuid>) -> Span {
+    let span = tracing::debug_span!("bond", cid = field::Empty, peer = field::Empty);
+    if let Some(cid) = cid {
+        span.record("cid", field::display(cid));
+    }
+    span
+}
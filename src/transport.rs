@@ -0,0 +1,27 @@
+use std::io::{Read, Result as IoResult, Write};
+use std::net::TcpStream;
+use std::os::fd::AsFd;
+use std::os::unix::net::UnixStream;
+
+/// A byte stream the substreams of a bond can be made of.
+///
+/// The bond polls the file descriptor of every transport for readiness and
+/// switches it to nonblocking mode once the bond is established, reads and
+/// writes then failing with `WouldBlock` rather than blocking. Transports are
+/// driven from a dedicated thread in background mode.
+pub trait Transport: Read + Write + AsFd + Send + 'static {
+    /// Moves the transport into or out of nonblocking mode.
+    fn set_nonblocking(&self, nonblocking: bool) -> IoResult<()>;
+}
+
+impl Transport for TcpStream {
+    fn set_nonblocking(&self, nonblocking: bool) -> IoResult<()> {
+        TcpStream::set_nonblocking(self, nonblocking)
+    }
+}
+
+impl Transport for UnixStream {
+    fn set_nonblocking(&self, nonblocking: bool) -> IoResult<()> {
+        UnixStream::set_nonblocking(self, nonblocking)
+    }
+}
@@ -0,0 +1,235 @@
+use std::io::Result as IoResult;
+use std::os::fd::AsRawFd;
+use std::time::Duration;
+
+use io_uring::{opcode, types, IoUring};
+
+use crate::bond::{IoCounters, Substream};
+use crate::trace::io_trace;
+use crate::transport::Transport;
+
+const OP_RX: u64 = 0;
+const OP_TX: u64 = 1;
+const NOTIFY: u64 = u64::MAX;
+
+/// Substream I/O performed through io_uring.
+///
+/// The receive and transmission buffers of every substream are registered
+/// with the ring, buffer `2 * id` being the receive one and `2 * id + 1` the
+/// transmission one of substream `id`. Reads and writes are queued as fixed
+/// buffer operations, at most one per direction and substream, and they are
+/// all submitted at once when the bond waits for their completions.
+///
+/// The bond is woken up by `Poller::notify` as well, the poller fd being
+/// polled through the ring.
+pub(crate) struct Uring {
+    ring: IoUring,
+    rx_inflight: Vec<bool>,
+    tx_inflight: Vec<bool>,
+    notify_inflight: bool,
+    events: polling::Events,
+}
+
+impl Uring {
+    pub(crate) fn new<T: Transport>(substreams: &mut [Substream<T>]) -> IoResult<Uring> {
+        let entries = (4 * substreams.len() + 2).next_power_of_two() as u32;
+        let ring = IoUring::new(entries)?;
+        let iovecs: Vec<libc::iovec> = substreams
+            .iter_mut()
+            .flat_map(|s| [s.rx.iovec(), s.tx.iovec()])
+            .collect();
+        // SAFETY: the buffers are allocated once and never move, and the
+        // operations in flight on them are cancelled before the substreams
+        // are dropped.
+        unsafe { ring.submitter().register_buffers(&iovecs)? };
+        for s in substreams.iter() {
+            // Blocking sockets let io_uring arm its internal poll rather than
+            // failing the operations with `EAGAIN`.
+            s.link.stream.set_nonblocking(false)?;
+        }
+        Ok(Uring {
+            ring,
+            rx_inflight: vec![false; substreams.len()],
+            tx_inflight: vec![false; substreams.len()],
+            notify_inflight: false,
+            events: polling::Events::new(),
+        })
+    }
+
+    fn push(&mut self, entry: &io_uring::squeue::Entry) -> IoResult<()> {
+        // SAFETY: the buffers referenced by the entry are registered and
+        // pinned until the operation completes.
+        while unsafe { self.ring.submission().push(entry) }.is_err() {
+            match self.ring.submit() {
+                Ok(_) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Queues a read into the free space of the receive buffer of substream
+    /// `id`, unless one is already in flight.
+    pub(crate) fn submit_rx<T: Transport>(&mut self, id: usize, s: &mut Substream<T>, counters: &mut IoCounters) -> IoResult<()> {
+        if self.rx_inflight[id] || s.eof || s.rx.is_full() {
+            return Ok(());
+        }
+        let fd = s.link.stream.as_fd().as_raw_fd();
+        let spare = s.rx.spare();
+        let entry = opcode::ReadFixed::new(types::Fd(fd), spare.as_mut_ptr(), spare.len() as u32, (2 * id) as u16)
+            .build()
+            .user_data(((id as u64) << 1) | OP_RX);
+        s.rx.pinned = true;
+        self.rx_inflight[id] = true;
+        counters.reads += 1;
+        self.push(&entry)
+    }
+
+    /// Queues a write of the frames scheduled on substream `id`, unless one
+    /// is already in flight.
+    pub(crate) fn submit_tx<T: Transport>(&mut self, id: usize, s: &mut Substream<T>, counters: &mut IoCounters) -> IoResult<()> {
+        if self.tx_inflight[id] || s.tx.is_empty() {
+            return Ok(());
+        }
+        let data = s.tx.data();
+        let entry = opcode::WriteFixed::new(types::Fd(s.link.stream.as_fd().as_raw_fd()), data.as_ptr(), data.len() as u32, (2 * id + 1) as u16)
+            .build()
+            .user_data(((id as u64) << 1) | OP_TX);
+        s.tx.pinned = true;
+        self.tx_inflight[id] = true;
+        counters.writes += 1;
+        self.push(&entry)
+    }
+
+    /// Submits the queued operations, along with reads on every substream
+    /// with room to receive, and waits for at least one of them to complete.
+    /// Completions are applied to the substream buffers before returning,
+    /// along with the first substream whose operation failed, if any, and
+    /// its error.
+    pub(crate) fn wait<T: Transport>(
+        &mut self,
+        substreams: &mut [Substream<T>],
+        poller: &polling::Poller,
+        counters: &mut IoCounters,
+        timeout: Option<Duration>,
+    ) -> IoResult<Option<(usize, std::io::Error)>> {
+        for (id, s) in substreams.iter_mut().enumerate() {
+            self.submit_rx(id, s, counters)?;
+        }
+        if !self.notify_inflight {
+            let entry = opcode::PollAdd::new(types::Fd(poller.as_raw_fd()), libc::POLLIN as u32)
+                .build()
+                .user_data(NOTIFY);
+            self.push(&entry)?;
+            self.notify_inflight = true;
+        }
+        io_trace!("Submitting and waiting for completions");
+        let res = match timeout {
+            Some(t) => {
+                let ts = types::Timespec::from(t);
+                let args = types::SubmitArgs::new().timespec(&ts);
+                self.ring.submitter().submit_with_args(1, &args)
+            }
+            None => self.ring.submit_and_wait(1),
+        };
+        counters.polls += 1;
+        counters.syscalls += 1;
+        match res {
+            Ok(_) => {}
+            Err(e) if e.raw_os_error() == Some(libc::ETIME) || e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+        self.complete(substreams, poller)
+    }
+
+    fn complete<T>(&mut self, substreams: &mut [Substream<T>], poller: &polling::Poller) -> IoResult<Option<(usize, std::io::Error)>> {
+        let mut failed = None;
+        let completions: Vec<(u64, i32)> = self.ring.completion().map(|c| (c.user_data(), c.result())).collect();
+        for (user_data, res) in completions {
+            io_trace!(user_data, res, "Completion");
+            if user_data == NOTIFY {
+                self.notify_inflight = false;
+                // Waiting on the poller consumes the notification.
+                self.events.clear();
+                poller.wait(&mut self.events, Some(Duration::ZERO))?;
+                continue;
+            }
+            let id = (user_data >> 1) as usize;
+            let s = &mut substreams[id];
+            if user_data & 1 == OP_RX {
+                self.rx_inflight[id] = false;
+                s.rx.pinned = false;
+                match res {
+                    0 => s.eof = true,
+                    n if n > 0 => s.filled(n as usize),
+                    _ => failed = failed.or(failure(res).map(|e| (id, e))),
+                }
+            } else {
+                self.tx_inflight[id] = false;
+                s.tx.pinned = false;
+                match res {
+                    0 => failed = failed.or(Some((id, std::io::ErrorKind::WriteZero.into()))),
+                    n if n > 0 => s.tx.consume(n as usize),
+                    _ => failed = failed.or(failure(res).map(|e| (id, e))),
+                }
+            }
+        }
+        Ok(failed)
+    }
+
+    /// Cancels the operations in flight and waits for them to complete, so
+    /// that the kernel no longer accesses the buffers. Returns `false` if
+    /// that cannot be confirmed, in which case the buffers must never be
+    /// freed.
+    pub(crate) fn cancel<T>(&mut self, substreams: &mut [Substream<T>]) -> bool {
+        let mut inflight: Vec<u64> = Vec::new();
+        for id in 0..substreams.len() {
+            if self.rx_inflight[id] {
+                inflight.push(((id as u64) << 1) | OP_RX);
+            }
+            if self.tx_inflight[id] {
+                inflight.push(((id as u64) << 1) | OP_TX);
+            }
+        }
+        if self.notify_inflight {
+            inflight.push(NOTIFY);
+        }
+        for user_data in inflight.iter() {
+            let entry = opcode::AsyncCancel::new(*user_data).build().user_data(user_data ^ (1 << 62));
+            if let Err(e) = self.push(&entry) {
+                tracing::debug!(error = %e, "Cannot cancel the operations in flight");
+                return false;
+            }
+        }
+        let mut pending = inflight.len();
+        while pending > 0 {
+            match self.ring.submit_and_wait(1) {
+                Ok(_) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => {
+                    tracing::debug!(error = %e, "Cannot wait for the operations in flight to be cancelled");
+                    return false;
+                }
+            }
+            pending -= self
+                .ring
+                .completion()
+                .filter(|c| inflight.contains(&c.user_data()))
+                .count();
+        }
+        self.rx_inflight.fill(false);
+        self.tx_inflight.fill(false);
+        self.notify_inflight = false;
+        true
+    }
+}
+
+/// Maps the result of a failed operation to an error, `None` if the
+/// operation can simply be submitted again.
+fn failure(res: i32) -> Option<std::io::Error> {
+    match -res {
+        libc::EAGAIN | libc::EINTR | libc::ECANCELED => None,
+        errno => Some(std::io::Error::from_raw_os_error(errno)),
+    }
+}
@@ -0,0 +1,113 @@
+//! The socket accessors of bonds and of their listeners.
+
+mod common;
+
+use std::net::{TcpListener, TcpStream};
+
+use bond_tcp::{BondConfig, BondStream, BondTcpListener, BondTcpStream, Role};
+use socket2::SockRef;
+use common::{background, payload, send, tcp_bond_with};
+
+#[test]
+fn both_sides_agree_on_the_substream_addresses() {
+    let mut listener = BondTcpListener::bind("127.0.0.1:0", 3).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handle = std::thread::spawn(move || BondTcpStream::connect(addr).unwrap());
+    let (server, _) = listener.accept().unwrap();
+    let client = handle.join().unwrap();
+    assert_eq!(client.peer_addr().unwrap(), addr);
+    assert_eq!(server.local_addr().unwrap(), addr);
+    assert_eq!(server.peer_addr().unwrap(), client.local_addr().unwrap());
+    let sources = client.substream_local_addrs().unwrap();
+    assert_eq!(sources.len(), 3);
+    assert_eq!(server.substream_peer_addrs().unwrap(), sources);
+    assert_eq!(client.substream_peer_addrs().unwrap(), vec![addr; 3]);
+}
+
+#[test]
+fn setters_apply_to_every_substream() {
+    for config in [BondConfig::default(), background(64 * 1024)] {
+        let (client, mut server) = tcp_bond_with(4, &config, &config);
+        assert!(client.nodelay().unwrap());
+        client.set_nodelay(false).unwrap();
+        assert!(!client.nodelay().unwrap());
+        client.set_ttl(42).unwrap();
+        assert_eq!(client.ttl().unwrap(), 42);
+        assert!(client.take_error().unwrap().is_none());
+        send(client, &mut server, &payload(100_000));
+    }
+}
+
+#[test]
+fn getters_fail_when_the_substreams_disagree() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (connector, acceptor): (Vec<TcpStream>, Vec<TcpStream>) =
+        (0..2).map(|_| (TcpStream::connect(addr).unwrap(), listener.accept().unwrap().0)).unzip();
+    connector[1].set_ttl(7).unwrap();
+    let handle = std::thread::spawn(move || BondStream::from_streams(acceptor, Role::Acceptor).unwrap());
+    let client = BondStream::from_streams(connector, Role::Connector).unwrap();
+    let _server = handle.join().unwrap();
+    assert!(client.ttl().is_err());
+    client.set_ttl(7).unwrap();
+    assert_eq!(client.ttl().unwrap(), 7);
+}
+
+#[test]
+fn the_ttl_of_ipv6_substreams_is_their_hop_limit() {
+    let listener = TcpListener::bind("[::1]:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (connector, acceptor): (Vec<TcpStream>, Vec<TcpStream>) =
+        (0..2).map(|_| (TcpStream::connect(addr).unwrap(), listener.accept().unwrap().0)).unzip();
+    let sockets: Vec<TcpStream> = connector.iter().map(|s| s.try_clone().unwrap()).collect();
+    let handle = std::thread::spawn(move || BondStream::from_streams(acceptor, Role::Acceptor).unwrap());
+    let client = BondStream::from_streams(connector, Role::Connector).unwrap();
+    let mut server = handle.join().unwrap();
+    client.set_ttl(21).unwrap();
+    assert_eq!(client.ttl().unwrap(), 21);
+    for socket in sockets.iter() {
+        assert_eq!(SockRef::from(socket).unicast_hops_v6().unwrap(), 21);
+    }
+    send(client, &mut server, &payload(10));
+
+    let mut listener = BondTcpListener::bind_with("[::1]:0", 2, BondConfig::default()).unwrap();
+    listener.set_ttl(33).unwrap();
+    assert_eq!(listener.ttl().unwrap(), 33);
+    let addr = listener.local_addr().unwrap();
+    let handle = std::thread::spawn(move || BondTcpStream::connect(addr).unwrap());
+    let (mut server, _) = listener.accept().unwrap();
+    assert_eq!(server.ttl().unwrap(), 33);
+    send(handle.join().unwrap(), &mut server, &payload(10));
+}
+
+#[test]
+fn accepted_bonds_get_the_ttl_of_the_listener() {
+    let mut listener = BondTcpListener::bind_multi(&["127.0.0.1:0", "127.0.0.2:0"], 2, BondConfig::default()).unwrap();
+    listener.set_ttl(33).unwrap();
+    assert_eq!(listener.ttl().unwrap(), 33);
+    assert!(listener.take_error().unwrap().is_none());
+    let addrs = listener.local_addrs().unwrap();
+    let handle = std::thread::spawn(move || BondTcpStream::connect_multi(&addrs, &BondConfig::default()).unwrap());
+    let (mut server, _) = listener.accept().unwrap();
+    assert_eq!(server.ttl().unwrap(), 33);
+    send(handle.join().unwrap(), &mut server, &payload(10));
+}
+
+#[test]
+fn nonblocking_listeners_keep_the_bonds_being_established() {
+    for addrs in [vec!["127.0.0.1:0"], vec!["127.0.0.1:0", "127.0.0.2:0"]] {
+        let mut listener = BondTcpListener::bind_multi(&addrs, 2, BondConfig::default()).unwrap();
+        listener.set_nonblocking(true).unwrap();
+        assert_eq!(listener.accept().map(|_| ()).unwrap_err().kind(), std::io::ErrorKind::WouldBlock);
+        let addr = listener.local_addr().unwrap();
+        let handle = std::thread::spawn(move || BondTcpStream::connect(addr).unwrap());
+        let (mut server, _) = loop {
+            match listener.accept() {
+                Ok(accepted) => break accepted,
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => std::thread::sleep(std::time::Duration::from_millis(1)),
+                Err(e) => panic!("{e}"),
+            }
+        };
+        send(handle.join().unwrap(), &mut server, &payload(10_000));
+    }
+}
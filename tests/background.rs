@@ -0,0 +1,67 @@
+//! Bonds driven by a background I/O thread.
+
+mod common;
+
+use std::io::{Read, Write};
+
+use common::{background, payload, send, tcp_bond_with};
+
+#[test]
+fn round_trip_through_queues_smaller_than_the_data() {
+    let config = background(4096);
+    let (client, mut server) = tcp_bond_with(3, &config, &config);
+    let data = payload(1 << 20);
+    let mut client = send(client, &mut server, &data);
+    send(server, &mut client, &data);
+}
+
+#[test]
+fn writes_return_once_queued() {
+    let config = background(64 * 1024);
+    let (mut client, mut server) = tcp_bond_with(2, &config, &config);
+    let data = payload(1 << 20);
+    // The server does not read yet, the write only fills the queue.
+    let n = client.write(&data).unwrap();
+    assert!(n > 0 && n <= 64 * 1024, "wrote {n} bytes");
+    client.flush().unwrap();
+    let mut received = vec![0u8; n];
+    server.read_exact(&mut received).unwrap();
+    assert!(received == data[..n]);
+}
+
+#[test]
+fn dropping_the_bond_sends_what_is_queued_first() {
+    let config = background(64 * 1024);
+    let (mut client, mut server) = tcp_bond_with(3, &config, &config);
+    let data = payload(1 << 20);
+    let sent = data.clone();
+    let handle = std::thread::spawn(move || {
+        client.write_all(&sent).unwrap();
+    });
+    let mut received = Vec::new();
+    server.read_to_end(&mut received).unwrap();
+    handle.join().unwrap();
+    assert!(received == data);
+}
+
+#[test]
+fn reads_end_once_the_peer_drops_the_bond() {
+    let config = background(8192);
+    let (mut client, mut server) = tcp_bond_with(3, &config, &config);
+    // Less than what `read_to_end` asks for at once.
+    let data = payload(10_001);
+    let sent = data.clone();
+    let handle = std::thread::spawn(move || client.write_all(&sent).unwrap());
+    let mut received = Vec::new();
+    server.read_to_end(&mut received).unwrap();
+    handle.join().unwrap();
+    assert!(received == data, "received {} bytes out of {}", received.len(), data.len());
+}
+
+#[test]
+fn background_bonds_talk_to_inline_ones() {
+    let (client, mut server) = tcp_bond_with(3, &background(8192), &Default::default());
+    let data = payload(300_000);
+    let mut client = send(client, &mut server, &data);
+    send(server, &mut client, &data);
+}
@@ -0,0 +1,51 @@
+//! Reads served from the buffers of the substreams.
+
+mod common;
+
+use std::io::{BufRead, BufReader, Read, Write};
+
+use common::{payload, tcp_bond};
+
+#[test]
+fn small_writes_read_back_as_lines() {
+    let (mut client, server) = tcp_bond(3);
+    let handle = std::thread::spawn(move || {
+        for i in 0..1000 {
+            writeln!(client, "line {i}").unwrap();
+        }
+    });
+    let lines: Vec<String> = BufReader::new(server).lines().map(Result::unwrap).collect();
+    handle.join().unwrap();
+    assert_eq!(lines.len(), 1000);
+    assert!(lines.iter().enumerate().all(|(i, line)| *line == format!("line {i}")));
+}
+
+#[test]
+fn fill_buf_stops_at_the_end_of_the_frame() {
+    let (mut client, mut server) = tcp_bond(2);
+    client.write_all(b"first").unwrap();
+    client.write_all(b"second").unwrap();
+    // Both frames are buffered by now, but a call only sees the current one.
+    assert_eq!(server.fill_buf().unwrap(), b"first");
+    server.consume(2);
+    assert_eq!(server.fill_buf().unwrap(), b"rst");
+    // Consuming past the frame stops at its end.
+    server.consume(100);
+    assert_eq!(server.fill_buf().unwrap(), b"second");
+    server.consume(6);
+    drop(client);
+    assert!(server.fill_buf().unwrap().is_empty());
+}
+
+#[test]
+fn reads_end_once_the_peer_drops_the_bond() {
+    let (mut client, mut server) = tcp_bond(3);
+    // Less than what `read_to_end` asks for at once.
+    let data = payload(10_001);
+    let sent = data.clone();
+    let handle = std::thread::spawn(move || client.write_all(&sent).unwrap());
+    let mut received = Vec::new();
+    server.read_to_end(&mut received).unwrap();
+    handle.join().unwrap();
+    assert!(received == data, "received {} bytes out of {}", received.len(), data.len());
+}
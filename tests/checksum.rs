@@ -0,0 +1,64 @@
+//! Frames carrying a CRC32C negotiated when the bond is established.
+
+mod common;
+
+use std::io::{ErrorKind, Read, Write};
+use std::net::TcpStream;
+
+use bond_tcp::{BondConfig, BondTcpListener, Checksum};
+use common::{background, payload, send, tcp_bond_with};
+
+fn crc32c() -> BondConfig {
+    BondConfig { checksum: Checksum::Crc32c, ..Default::default() }
+}
+
+#[test]
+fn checked_frames_round_trip_when_either_side_asks() {
+    for (client, server) in [(crc32c(), BondConfig::default()), (BondConfig::default(), crc32c())] {
+        let (client, mut server) = tcp_bond_with(3, &client, &server);
+        let mut client = send(client, &mut server, &payload(100_000));
+        send(server, &mut client, &payload(12_345));
+    }
+}
+
+#[test]
+fn checked_frames_round_trip_in_background() {
+    let config = BondConfig { checksum: Checksum::Crc32c, ..background(64 * 1024) };
+    let (client, mut server) = tcp_bond_with(2, &config, &config);
+    send(client, &mut server, &payload(1 << 20));
+}
+
+#[test]
+fn corrupted_frame_is_reported_with_its_location() {
+    let mut listener = BondTcpListener::bind_with("127.0.0.1:0", 1, crc32c()).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handle = std::thread::spawn(move || {
+        let mut peer = TcpStream::connect(addr).unwrap();
+        // Handshake of a peer asking for no checksum.
+        peer.write_all(&[[0u8; 16].as_slice(), &[0]].concat()).unwrap();
+        let mut reply = [0u8; 2];
+        peer.read_exact(&mut reply).unwrap();
+        assert_eq!(reply, [1, 1]);
+        // A valid frame followed by one whose payload got flipped.
+        for (i, data) in [b"hello", b"world"].iter().enumerate() {
+            let len = (data.len() as u32).to_le_bytes();
+            let crc = crc32c::crc32c_append(crc32c::crc32c(&len), *data);
+            let mut frame = [len, crc.to_le_bytes()].concat();
+            frame.extend_from_slice(*data);
+            if i == 1 {
+                *frame.last_mut().unwrap() ^= 0x01;
+            }
+            peer.write_all(&frame).unwrap();
+        }
+        peer
+    });
+    let (mut server, _) = listener.accept().unwrap();
+    let _peer = handle.join().unwrap();
+    let mut buf = [0u8; 5];
+    server.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"hello");
+    let err = server.read(&mut buf).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    let msg = err.to_string();
+    assert!(msg.contains("substream 0") && msg.contains("offset 13"), "unexpected error: {msg}");
+}
@@ -0,0 +1,126 @@
+//! Helpers shared by the integration tests.
+#![allow(dead_code)]
+
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use bond_tcp::{BondConfig, BondTcpListener, BondTcpStream, IoMode};
+
+/// Returns `len` bytes of a pattern that does not line up with fragments.
+pub fn payload(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 7 % 251) as u8).collect()
+}
+
+/// Returns a configuration driving the substreams from a background
+/// thread, through queues of `queue_size` bytes.
+pub fn background(queue_size: usize) -> BondConfig {
+    BondConfig { io_mode: IoMode::Background { queue_size }, ..Default::default() }
+}
+
+/// Bonds `n` TCP connections on the loopback interface, returns the
+/// connecting side and the accepting one.
+pub fn tcp_bond(n: u8) -> (BondTcpStream, BondTcpStream) {
+    tcp_bond_with(n, &BondConfig::default(), &BondConfig::default())
+}
+
+/// Bonds `n` TCP connections on the loopback interface, the connecting side
+/// with `client` and the accepting side with `server`.
+pub fn tcp_bond_with(n: u8, client: &BondConfig, server: &BondConfig) -> (BondTcpStream, BondTcpStream) {
+    let mut listener = BondTcpListener::bind_with("127.0.0.1:0", n, server.clone()).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let client = client.clone();
+    let handle = std::thread::spawn(move || BondTcpStream::connect_with(addr, &client).unwrap());
+    let (server, _) = listener.accept().unwrap();
+    (handle.join().unwrap(), server)
+}
+
+/// Writes and flushes `data` on `tx` from another thread while reading it
+/// from `rx`, checks it arrived intact and gives `tx` back.
+pub fn send<W: Write + Send + 'static>(mut tx: W, rx: &mut impl Read, data: &[u8]) -> W {
+    let sent = data.to_vec();
+    let handle = std::thread::spawn(move || {
+        tx.write_all(&sent).unwrap();
+        tx.flush().unwrap();
+        tx
+    });
+    let mut received = vec![0u8; data.len()];
+    rx.read_exact(&mut received).unwrap();
+    assert!(received == data, "the bytes received differ from the ones sent");
+    handle.join().unwrap()
+}
+
+/// Forwards every connection made to the returned address to `target`,
+/// recording the address each one comes from.
+pub fn forwarder(target: SocketAddr) -> (SocketAddr, Arc<Mutex<Vec<SocketAddr>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let sources = Arc::new(Mutex::new(Vec::new()));
+    let accepted = sources.clone();
+    std::thread::spawn(move || {
+        for inbound in listener.incoming() {
+            let inbound = inbound.unwrap();
+            let outbound = TcpStream::connect(target).unwrap();
+            accepted.lock().unwrap().push(inbound.peer_addr().unwrap());
+            for (mut from, mut to) in [(inbound.try_clone().unwrap(), outbound.try_clone().unwrap()), (outbound, inbound)] {
+                std::thread::spawn(move || {
+                    let _ = std::io::copy(&mut from, &mut to);
+                    let _ = to.shutdown(std::net::Shutdown::Write);
+                });
+            }
+        }
+    });
+    (addr, sources)
+}
+
+/// Forwards every connection made to the returned address to `target`,
+/// delaying what is forwarded by `delay` in both directions, as a path with
+/// a longer round-trip time would.
+pub fn delayed_forwarder(target: SocketAddr, delay: Duration) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    std::thread::spawn(move || {
+        for inbound in listener.incoming() {
+            let inbound = inbound.unwrap();
+            let outbound = TcpStream::connect(target).unwrap();
+            for (mut from, mut to) in [(inbound.try_clone().unwrap(), outbound.try_clone().unwrap()), (outbound, inbound)] {
+                let (tx, rx) = std::sync::mpsc::channel::<(Instant, Vec<u8>)>();
+                std::thread::spawn(move || {
+                    let mut buf = vec![0u8; 64 * 1024];
+                    while let Ok(n @ 1..) = from.read(&mut buf) {
+                        if tx.send((Instant::now() + delay, buf[..n].to_vec())).is_err() {
+                            break;
+                        }
+                    }
+                });
+                std::thread::spawn(move || {
+                    for (due, data) in rx {
+                        std::thread::sleep(due.saturating_duration_since(Instant::now()));
+                        if to.write_all(&data).is_err() {
+                            break;
+                        }
+                    }
+                    let _ = to.shutdown(std::net::Shutdown::Write);
+                });
+            }
+        }
+    });
+    addr
+}
+
+/// Bonds two TCP connections, the second one going through a path that is
+/// `delay` slower each way, the connecting side with `client` and the
+/// accepting side with `server`. Returns both sides and the index of the
+/// slow substream on the connecting side.
+pub fn skewed_bond(delay: Duration, client: &BondConfig, server: &BondConfig) -> (BondTcpStream, BondTcpStream, usize) {
+    let mut listener = BondTcpListener::bind_multi(&["127.0.0.1:0", "127.0.0.2:0"], 2, server.clone()).unwrap();
+    let addrs = listener.local_addrs().unwrap();
+    let slow = delayed_forwarder(addrs[1], delay);
+    let client = client.clone();
+    let handle = std::thread::spawn(move || BondTcpStream::connect_multi(&[addrs[0], slow], &client).unwrap());
+    let (server, _) = listener.accept().unwrap();
+    let client = handle.join().unwrap();
+    let index = client.substream_peer_addrs().unwrap().iter().position(|a| *a == slow).unwrap();
+    (client, server, index)
+}
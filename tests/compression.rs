@@ -0,0 +1,102 @@
+//! Frames whose payload is compressed, as negotiated when the bond is
+//! established.
+#![cfg(any(feature = "lz4", feature = "zstd"))]
+
+mod common;
+
+use std::io::{Read, Write};
+
+use bond_tcp::{BondConfig, Checksum, Compression};
+use common::{background, payload, send, tcp_bond_with};
+
+fn algorithms() -> Vec<Compression> {
+    vec![
+        #[cfg(feature = "lz4")]
+        Compression::Lz4,
+        #[cfg(feature = "zstd")]
+        Compression::Zstd,
+    ]
+}
+
+fn compressed(compression: Compression, config: BondConfig) -> BondConfig {
+    BondConfig { compression, ..config }
+}
+
+/// Returns `len` bytes no compressor can make any smaller.
+fn noise(len: usize) -> Vec<u8> {
+    let mut x = 0x2545_f491_4f6c_dd1du64;
+    (0..len)
+        .map(|_| {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            x as u8
+        })
+        .collect()
+}
+
+#[test]
+fn compressible_payload_takes_fewer_bytes_on_the_wire() {
+    for compression in algorithms() {
+        let config = compressed(compression, BondConfig::default());
+        let (client, mut server) = tcp_bond_with(3, &config, &config);
+        let data = payload(1 << 20);
+        let client = send(client, &mut server, &data);
+        let counters = client.io_counters();
+        assert_eq!(counters.payload_bytes, data.len() as u64);
+        assert!(counters.wire_bytes < counters.payload_bytes / 4, "{compression:?}: {counters:?}");
+        assert_eq!(server.io_counters().wire_bytes, counters.wire_bytes);
+    }
+}
+
+#[test]
+fn incompressible_fragments_are_sent_as_is() {
+    for compression in algorithms() {
+        let config = compressed(compression, BondConfig::default());
+        let (client, mut server) = tcp_bond_with(2, &config, &config);
+        // Noisy fragments between compressible ones.
+        let data = [payload(20_000), noise(50_000), payload(10_000)].concat();
+        let client = send(client, &mut server, &data);
+        let counters = client.io_counters();
+        assert!(counters.wire_bytes > 50_000 && counters.wire_bytes < counters.payload_bytes, "{compression:?}: {counters:?}");
+    }
+}
+
+#[test]
+fn compression_is_used_when_either_side_asks() {
+    for compression in algorithms() {
+        let asked = compressed(compression, BondConfig::default());
+        for (client, server) in [(asked.clone(), BondConfig::default()), (BondConfig::default(), asked.clone())] {
+            let (client, mut server) = tcp_bond_with(2, &client, &server);
+            let mut client = send(client, &mut server, &payload(100_000));
+            send(server, &mut client, &payload(100_000));
+            assert!(client.io_counters().wire_bytes < 100_000);
+        }
+    }
+}
+
+#[test]
+fn compressed_and_checked_frames_round_trip_in_background() {
+    for compression in algorithms() {
+        let config = BondConfig { checksum: Checksum::Crc32c, ..compressed(compression, background(64 * 1024)) };
+        let (client, mut server) = tcp_bond_with(3, &config, &config);
+        let data = [noise(30_000), payload(1 << 20)].concat();
+        let mut client = send(client, &mut server, &data);
+        send(server, &mut client, &data);
+    }
+}
+
+#[test]
+fn reads_end_once_the_peer_drops_a_compressed_bond() {
+    for compression in algorithms() {
+        let config = compressed(compression, BondConfig::default());
+        let (mut client, mut server) = tcp_bond_with(3, &config, &config);
+        let data = payload(123_457);
+        let sent = data.clone();
+        let handle = std::thread::spawn(move || client.write_all(&sent).unwrap());
+        let mut received = Vec::new();
+        server.read_to_end(&mut received).unwrap();
+        handle.join().unwrap();
+        assert!(received == data, "{compression:?}: received {} bytes out of {}", received.len(), data.len());
+    }
+}
@@ -0,0 +1,109 @@
+//! Frames encrypted with keys derived from a pre-shared key.
+
+mod common;
+
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+
+use bond_tcp::{BondConfig, BondTcpListener, BondTcpStream, Checksum, Heartbeat, PreSharedKey};
+use common::{background, payload, send, tcp_bond_with};
+
+fn encrypted(key: u8, config: BondConfig) -> BondConfig {
+    BondConfig { psk: Some(PreSharedKey::new([key; 32])), ..config }
+}
+
+#[test]
+fn encrypted_frames_round_trip() {
+    for config in [encrypted(1, BondConfig::default()), encrypted(1, background(64 * 1024))] {
+        let (client, mut server) = tcp_bond_with(4, &config, &config);
+        let data = payload(512 * 1024);
+        let mut client = send(client, &mut server, &data);
+        send(server, &mut client, &data);
+    }
+}
+
+#[test]
+fn encrypted_and_checked_frames_round_trip() {
+    let config = BondConfig { checksum: Checksum::Crc32c, ..encrypted(1, BondConfig::default()) };
+    let (client, mut server) = tcp_bond_with(3, &config, &config);
+    let mut client = send(client, &mut server, &payload(100_000));
+    send(server, &mut client, &payload(7));
+}
+
+#[cfg(feature = "lz4")]
+#[test]
+fn compressed_and_encrypted_frames_round_trip() {
+    let config = BondConfig { compression: bond_tcp::Compression::Lz4, ..encrypted(1, BondConfig::default()) };
+    let (client, mut server) = tcp_bond_with(3, &config, &config);
+    let client = send(client, &mut server, &payload(1 << 20));
+    assert!(client.io_counters().wire_bytes < 1 << 18, "{:?}", client.io_counters());
+}
+
+#[test]
+fn frames_of_another_key_fail_authentication() {
+    let (mut client, mut server) = tcp_bond_with(2, &encrypted(1, BondConfig::default()), &encrypted(2, BondConfig::default()));
+    client.write_all(b"hello").unwrap();
+    let err = server.read(&mut [0u8; 5]).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    assert!(err.to_string().contains("Frame 0 at offset 0 of substream 0 failed authentication"), "unexpected error: {err}");
+}
+
+#[test]
+fn control_frames_of_another_key_fail_authentication() {
+    let heartbeat = Some(Heartbeat { interval: Duration::from_millis(10), misses: 3 });
+    let (_client, mut server) =
+        tcp_bond_with(2, &encrypted(1, BondConfig { heartbeat, ..background(64 * 1024) }), &encrypted(2, BondConfig { heartbeat, ..Default::default() }));
+    // Nothing but the heartbeats of the idle client is sent.
+    let err = server.read(&mut [0u8; 5]).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    assert!(err.to_string().contains("Control frame at offset 0 of substream"), "unexpected error: {err}");
+    assert!(err.to_string().contains("failed authentication"), "unexpected error: {err}");
+}
+
+#[test]
+fn downgraded_options_fail_authentication() {
+    let config = BondConfig { checksum: Checksum::Crc32c, ..encrypted(1, BondConfig::default()) };
+    let mut listener = BondTcpListener::bind_with("127.0.0.1:0", 1, encrypted(1, BondConfig::default())).unwrap();
+    let target = listener.local_addr().unwrap();
+    // Clears the checksum asked for in the proposal, which follows the
+    // identifier of the connection attempt, and relays the rest untouched.
+    let proxy = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = proxy.local_addr().unwrap();
+    std::thread::spawn(move || {
+        let (mut inbound, _) = proxy.accept().unwrap();
+        let mut outbound = TcpStream::connect(target).unwrap();
+        let mut hello = [0u8; 17];
+        inbound.read_exact(&mut hello).unwrap();
+        hello[16] &= !0x01;
+        outbound.write_all(&hello).unwrap();
+        for (mut from, mut to) in [(inbound.try_clone().unwrap(), outbound.try_clone().unwrap()), (outbound, inbound)] {
+            std::thread::spawn(move || {
+                let _ = std::io::copy(&mut from, &mut to);
+                let _ = to.shutdown(std::net::Shutdown::Write);
+            });
+        }
+    });
+    let handle = std::thread::spawn(move || {
+        let mut client = BondTcpStream::connect_with(addr, &config).unwrap();
+        client.write_all(b"hello").unwrap();
+        client
+    });
+    let (mut server, _) = listener.accept().unwrap();
+    let err = server.read(&mut [0u8; 5]).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    assert!(err.to_string().contains("Frame 0 at offset 0 of substream 0 failed authentication"), "unexpected error: {err}");
+    handle.join().unwrap();
+}
+
+#[test]
+fn both_sides_have_to_encrypt() {
+    for (client, server) in [(encrypted(1, BondConfig::default()), BondConfig::default()), (BondConfig::default(), encrypted(1, BondConfig::default()))] {
+        let mut listener = BondTcpListener::bind_with("127.0.0.1:0", 2, server).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = std::thread::spawn(move || BondTcpStream::connect_with(addr, &client).map(|_| ()));
+        let err = listener.accept().map(|_| ()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+        assert!(handle.join().unwrap().is_err());
+    }
+}
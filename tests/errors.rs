@@ -0,0 +1,91 @@
+//! Failures of bonds, as carried by their I/O errors.
+
+mod common;
+
+use std::io::{ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::os::unix::net::UnixStream;
+
+use bond_tcp::{BondConfig, BondError, BondStream, BondTcpListener, BondTcpStream, PreSharedKey, Role};
+use common::tcp_bond;
+
+#[test]
+fn unresolvable_addresses_are_reported() {
+    let err = BondTcpStream::connect("bond-tcp.invalid:80").map(|_| ()).unwrap_err();
+    assert!(matches!(BondError::of(&err), Some(BondError::Resolution { .. })), "unexpected error: {err:?}");
+}
+
+#[test]
+fn handshake_failures_tell_what_the_sides_disagree_on() {
+    let (connector, mut acceptor): (Vec<_>, Vec<_>) = (0..2).map(|_| UnixStream::pair().unwrap()).unzip();
+    // The acceptor is given one connection less than the connector bonds.
+    let spare = acceptor.pop();
+    let handle = std::thread::spawn(move || BondStream::from_streams(connector, Role::Connector).map(|_| ()));
+    let err = BondStream::from_streams(acceptor, Role::Acceptor).map(|_| ()).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    assert!(matches!(BondError::of(&err), Some(BondError::HandshakeMismatch { .. })), "unexpected error: {err:?}");
+    drop(spare);
+    let _ = handle.join().unwrap();
+
+    let mut listener = BondTcpListener::bind_with("127.0.0.1:0", 2, BondConfig { psk: Some(PreSharedKey::new([3; 32])), ..Default::default() }).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handle = std::thread::spawn(move || BondTcpStream::connect(addr).map(|_| ()));
+    let err = listener.accept().map(|_| ()).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+    assert!(matches!(BondError::of(&err), Some(BondError::AuthenticationFailed { substream: None, .. })), "unexpected error: {err:?}");
+    assert!(handle.join().unwrap().is_err());
+}
+
+#[test]
+fn connections_failing_to_join_leave_the_bond_pending() {
+    let mut listener = BondTcpListener::bind_with("127.0.0.1:0", 2, BondConfig::default()).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handle = std::thread::spawn(move || {
+        // Proposes no option under an identifier of its own, then reads the
+        // number of connections, the settled options and the bond to join.
+        let mut first = TcpStream::connect(addr).unwrap();
+        first.write_all(&[[7u8; 16].as_slice(), &[0]].concat()).unwrap();
+        let mut reply = [0u8; 2 + 16];
+        first.read_exact(&mut reply).unwrap();
+        let cid = &reply[2..];
+        let mut stray = TcpStream::connect(addr).unwrap();
+        stray.write_all(&[cid, &[5]].concat()).unwrap();
+        let mut second = TcpStream::connect(addr).unwrap();
+        second.write_all(&[cid, &[1]].concat()).unwrap();
+        (first, second)
+    });
+    let err = listener.accept().map(|_| ()).unwrap_err();
+    assert!(err.to_string().contains("Connection 5 cannot join bond"), "unexpected error: {err}");
+    listener.accept().unwrap();
+    handle.join().unwrap();
+}
+
+#[test]
+fn substream_failures_name_the_substream_and_its_peer() {
+    let (mut client, server) = tcp_bond(3);
+    let peers = client.substream_peer_addrs().unwrap();
+    drop(server);
+    let err = loop {
+        if let Err(e) = client.write_all(&[0u8; 64 * 1024]) {
+            break e;
+        }
+    };
+    match BondError::of(&err) {
+        Some(BondError::SubstreamFailed { index, peer, error }) => {
+            assert_eq!(*peer, Some(peers[*index]));
+            assert_eq!(err.kind(), error.kind());
+        }
+        _ => panic!("unexpected error: {err:?}"),
+    }
+}
+
+#[test]
+fn unsupported_operations_fail_rather_than_panic() {
+    let (client, _server) = tcp_bond(1);
+    assert_eq!(client.try_clone().map(|_| ()).unwrap_err().kind(), ErrorKind::Unsupported);
+    assert_eq!(client.peek(&mut [0u8; 1]).unwrap_err().kind(), ErrorKind::Unsupported);
+    let listener = BondTcpListener::bind("127.0.0.1:0", 1).unwrap();
+    let mut incoming = listener.incoming();
+    assert_eq!(incoming.next().unwrap().map(|_| ()).unwrap_err().kind(), ErrorKind::Unsupported);
+    assert!(incoming.next().is_none());
+}
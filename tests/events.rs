@@ -0,0 +1,149 @@
+//! Events reported to the observers of bonds.
+
+mod common;
+
+use std::io::{Read, Write};
+use std::os::fd::{AsFd, BorrowedFd};
+use std::os::unix::net::UnixStream;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use bond_tcp::{BondConfig, BondError, BondEvent, BondEventKind, BondStream, BondTcpListener, BondTcpStream, Observer, Role, SocketOptions, Transport};
+use common::{payload, send, tcp_bond_with};
+
+/// Returns an observer recording the events it is notified of.
+fn recorder() -> (Observer, Arc<Mutex<Vec<BondEvent>>>) {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let recorded = events.clone();
+    (Observer::new(move |e| recorded.lock().unwrap().push(e.clone())), events)
+}
+
+fn kinds(events: &Mutex<Vec<BondEvent>>) -> Vec<(BondEventKind, Option<usize>)> {
+    events.lock().unwrap().iter().map(|e| (e.kind.clone(), e.substream)).collect()
+}
+
+#[test]
+fn both_sides_report_the_life_of_a_bond() {
+    let (client_observer, client_events) = recorder();
+    let (server_observer, server_events) = recorder();
+    let mut listener = BondTcpListener::bind_with("127.0.0.1:0", 3, BondConfig { observer: Some(server_observer), ..Default::default() }).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let config = BondConfig { observer: Some(client_observer), ..Default::default() };
+    let handle = std::thread::spawn(move || BondTcpStream::connect_with(addr, &config).unwrap());
+    let (mut server, _) = listener.accept().unwrap();
+    let client = handle.join().unwrap();
+    send(client, &mut server, &payload(10_000));
+    drop(server);
+    use BondEventKind::*;
+    for events in [&client_events, &server_events] {
+        assert_eq!(
+            kinds(events),
+            vec![
+                (SubstreamConnected, Some(0)),
+                (HandshakeCompleted, None),
+                (SubstreamConnected, Some(1)),
+                (SubstreamConnected, Some(2)),
+                (BondFormed, None),
+                (BondClosed, None),
+            ]
+        );
+    }
+    let cid = client_events.lock().unwrap()[4].cid;
+    assert!(cid.is_some());
+    assert!(server_events.lock().unwrap()[1..].iter().all(|e| e.cid == cid));
+    let connected = &client_events.lock().unwrap()[2];
+    assert_eq!(connected.peer, Some(addr));
+}
+
+/// A Unix socket whose reads fail once `failed` is set.
+struct Failing {
+    socket: UnixStream,
+    failed: Arc<AtomicBool>,
+}
+
+impl Read for Failing {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.failed.load(Ordering::Relaxed) {
+            return Err(std::io::Error::other("injected failure"));
+        }
+        self.socket.read(buf)
+    }
+}
+
+impl Write for Failing {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.socket.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.socket.flush()
+    }
+}
+
+impl AsFd for Failing {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.socket.as_fd()
+    }
+}
+
+impl Transport for Failing {
+    fn set_nonblocking(&self, nonblocking: bool) -> std::io::Result<()> {
+        self.socket.set_nonblocking(nonblocking)
+    }
+}
+
+#[test]
+fn failing_substreams_are_reported() {
+    let (connector, acceptor): (Vec<UnixStream>, Vec<UnixStream>) = (0..2).map(|_| UnixStream::pair().unwrap()).unzip();
+    let failed = Arc::new(AtomicBool::new(false));
+    // Only the second substream of the accepting side fails.
+    let flags = [Arc::new(AtomicBool::new(false)), failed.clone()];
+    let acceptor: Vec<Failing> = acceptor.into_iter().zip(flags).map(|(socket, failed)| Failing { socket, failed }).collect();
+    let connector: Vec<Failing> = connector.into_iter().map(|socket| Failing { socket, failed: Arc::new(AtomicBool::new(false)) }).collect();
+    let (observer, events) = recorder();
+    let config = BondConfig { observer: Some(observer), ..Default::default() };
+    let handle = std::thread::spawn(move || BondStream::from_streams(connector, Role::Connector).unwrap());
+    let mut server = BondStream::from_streams_with(acceptor, Role::Acceptor, &config).unwrap();
+    let mut client = handle.join().unwrap();
+    failed.store(true, Ordering::Relaxed);
+    client.write_all(&payload(20_000)).unwrap();
+    client.flush().unwrap();
+    let err = server.read_exact(&mut [0u8; 20_000]).unwrap_err();
+    assert_eq!(err.to_string(), "Substream 1 failed: injected failure");
+    assert!(matches!(BondError::of(&err), Some(BondError::SubstreamFailed { index: 1, peer: None, .. })));
+    let failure = events.lock().unwrap().last().cloned().unwrap();
+    assert_eq!(failure.kind, BondEventKind::SubstreamFailed { error: "Substream 1 failed: injected failure".into() });
+    assert_eq!(failure.substream, Some(1));
+    assert_eq!(failure.cid, None);
+}
+
+#[test]
+fn substreams_that_take_nothing_are_reported_stalled() {
+    let (observer, events) = recorder();
+    let small = SocketOptions { send_buffer_size: Some(64 * 1024), recv_buffer_size: Some(64 * 1024), ..Default::default() };
+    let config = BondConfig {
+        socket_options: vec![small],
+        observer: Some(observer),
+        stall_timeout: Some(Duration::from_millis(20)),
+        ..Default::default()
+    };
+    let server_config = BondConfig { socket_options: config.socket_options.clone(), ..Default::default() };
+    let (client, mut server) = tcp_bond_with(2, &config, &server_config);
+    let data = payload(1 << 20);
+    let sent = data.clone();
+    // The server reads nothing until the writer has been blocked for a while.
+    let handle = std::thread::spawn(move || {
+        let mut client = client;
+        client.write_all(&sent).unwrap();
+        client.flush().unwrap();
+        client
+    });
+    std::thread::sleep(Duration::from_millis(200));
+    let mut received = vec![0u8; data.len()];
+    server.read_exact(&mut received).unwrap();
+    assert!(received == data);
+    drop(handle.join().unwrap());
+    let stalls = kinds(&events).into_iter().filter(|(kind, _)| *kind == BondEventKind::SubstreamStalled).count();
+    assert!(stalls >= 1, "{:?}", kinds(&events));
+}
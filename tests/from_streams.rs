@@ -0,0 +1,72 @@
+//! Bonds built out of connections established beforehand.
+
+mod common;
+
+use std::io::ErrorKind;
+use std::net::{TcpListener, TcpStream};
+
+use bond_tcp::{BondConfig, BondTcpListener, BondTcpStream, Checksum, PreSharedKey, Role};
+use common::{payload, send};
+
+/// Opens `n` connections on the loopback interface, returns the connecting
+/// ends and the accepting ones, in the same order.
+fn connections(n: usize) -> (Vec<TcpStream>, Vec<TcpStream>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    (0..n).map(|_| (TcpStream::connect(addr).unwrap(), listener.accept().unwrap().0)).unzip()
+}
+
+/// Bonds the given connections on both sides at once.
+fn bond(
+    connector: Vec<TcpStream>,
+    acceptor: Vec<TcpStream>,
+    config: &BondConfig,
+) -> (std::io::Result<BondTcpStream>, std::io::Result<BondTcpStream>) {
+    let client = config.clone();
+    let handle = std::thread::spawn(move || BondTcpStream::from_streams_with(connector, Role::Connector, &client));
+    let server = BondTcpStream::from_streams_with(acceptor, Role::Acceptor, config);
+    (handle.join().unwrap(), server)
+}
+
+#[test]
+fn established_connections_are_bonded_in_any_order() {
+    let (connector, mut acceptor) = connections(4);
+    acceptor.reverse();
+    let config = BondConfig { checksum: Checksum::Crc32c, psk: Some(PreSharedKey::new([3; 32])), ..Default::default() };
+    let (client, server) = bond(connector, acceptor, &config);
+    let (client, mut server) = (client.unwrap(), server.unwrap());
+    let data = payload(300_000);
+    let mut client = send(client, &mut server, &data);
+    send(server, &mut client, &data);
+}
+
+#[test]
+fn nonblocking_connections_are_bonded_with_the_default_configuration() {
+    let (connector, acceptor) = connections(2);
+    for s in connector.iter().chain(acceptor.iter()) {
+        s.set_nonblocking(true).unwrap();
+    }
+    let handle = std::thread::spawn(move || BondTcpStream::from_streams(connector, Role::Connector).unwrap());
+    let mut server = BondTcpStream::from_streams(acceptor, Role::Acceptor).unwrap();
+    send(handle.join().unwrap(), &mut server, &payload(50_000));
+}
+
+#[test]
+fn both_sides_have_to_bond_as_many_connections() {
+    let (connector, mut acceptor) = connections(3);
+    acceptor.pop();
+    let (_, server) = bond(connector, acceptor, &BondConfig::default());
+    let err = server.map(|_| ()).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    assert!(err.to_string().contains("out of 3, while 2 connections are given"), "unexpected error: {err}");
+}
+
+#[test]
+fn listeners_bound_beforehand_accept_bonds() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut listener = BondTcpListener::from_std(listener, 3, BondConfig::default()).unwrap();
+    let handle = std::thread::spawn(move || BondTcpStream::connect(addr).unwrap());
+    let (mut server, _) = listener.accept().unwrap();
+    send(handle.join().unwrap(), &mut server, &payload(100_000));
+}
@@ -0,0 +1,266 @@
+//! Heartbeats, and the failure of bonds whose substreams go silent.
+
+mod common;
+
+use std::io::{ErrorKind, Read, Write};
+use std::os::fd::{AsFd, BorrowedFd};
+use std::os::unix::net::UnixStream;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+
+use bond_tcp::{BondConfig, BondError, BondEvent, BondEventKind, BondStream, Checksum, Heartbeat, IoMode, Observer, PreSharedKey, Role, Transport};
+use common::{payload, send, tcp_bond_with};
+
+fn heartbeat(ms: u64) -> Option<Heartbeat> {
+    Some(Heartbeat { interval: Duration::from_millis(ms), misses: 3 })
+}
+
+/// A Unix socket whose path stops delivering what is written to it while
+/// `silent` is set, and delivers what it held back once it is cleared.
+struct SilentPath {
+    socket: UnixStream,
+    silent: Arc<AtomicBool>,
+    held: Vec<u8>,
+}
+
+impl Read for SilentPath {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.socket.read(buf)
+    }
+}
+
+impl Write for SilentPath {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.silent.load(Ordering::Relaxed) {
+            self.held.extend_from_slice(buf);
+            return Ok(buf.len());
+        }
+        while !self.held.is_empty() {
+            let n = self.socket.write(&self.held)?;
+            self.held.drain(..n);
+        }
+        self.socket.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.socket.flush()
+    }
+}
+
+impl AsFd for SilentPath {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.socket.as_fd()
+    }
+}
+
+impl Transport for SilentPath {
+    fn set_nonblocking(&self, nonblocking: bool) -> std::io::Result<()> {
+        self.socket.set_nonblocking(nonblocking)
+    }
+}
+
+/// A Unix socket whose path goes silent both ways once the connecting side
+/// wrote `budget` more bytes to it, writes on either side then failing as if
+/// the socket were full.
+struct DyingPath {
+    socket: UnixStream,
+    budget: Arc<Mutex<Option<usize>>>,
+    connector: bool,
+}
+
+impl Read for DyingPath {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.socket.read(buf)
+    }
+}
+
+impl Write for DyingPath {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut budget = self.budget.lock().unwrap();
+        let left = match *budget {
+            None => return self.socket.write(buf),
+            Some(left) if self.connector => left.min(buf.len()),
+            Some(_) => 0,
+        };
+        if left == 0 {
+            return Err(ErrorKind::WouldBlock.into());
+        }
+        let n = self.socket.write(&buf[..left])?;
+        *budget = budget.map(|b| b - n);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.socket.flush()
+    }
+}
+
+impl AsFd for DyingPath {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.socket.as_fd()
+    }
+}
+
+impl Transport for DyingPath {
+    fn set_nonblocking(&self, nonblocking: bool) -> std::io::Result<()> {
+        self.socket.set_nonblocking(nonblocking)
+    }
+}
+
+/// Returns a bond of two substreams exchanging heartbeats, the path of the
+/// second one dying once the returned budget is set.
+fn dying_bond() -> (BondStream<DyingPath>, BondStream<DyingPath>, Arc<Mutex<Option<usize>>>) {
+    let budget = Arc::new(Mutex::new(None));
+    let mut connector = Vec::new();
+    let mut acceptor = Vec::new();
+    for id in 0..2 {
+        let (a, b) = UnixStream::pair().unwrap();
+        // The path of the first substream never dies.
+        let shared = if id == 1 { budget.clone() } else { Arc::new(Mutex::new(None)) };
+        connector.push(DyingPath { socket: a, budget: shared.clone(), connector: true });
+        acceptor.push(DyingPath { socket: b, budget: shared, connector: false });
+    }
+    let config = BondConfig { heartbeat: heartbeat(10), ..Default::default() };
+    let client = config.clone();
+    let handle = std::thread::spawn(move || BondStream::from_streams_with(connector, Role::Connector, &client).unwrap());
+    let server = BondStream::from_streams_with(acceptor, Role::Acceptor, &config).unwrap();
+    (handle.join().unwrap(), server, budget)
+}
+
+/// Returns a pair of connected Unix sockets for every flag, the path from
+/// the connecting side going silent while it is set.
+fn silent_paths(flags: &[Arc<AtomicBool>]) -> (Vec<SilentPath>, Vec<UnixStream>) {
+    let (connector, acceptor): (Vec<UnixStream>, Vec<UnixStream>) = flags.iter().map(|_| UnixStream::pair().unwrap()).unzip();
+    (connector.into_iter().zip(flags).map(|(socket, silent)| SilentPath { socket, silent: silent.clone(), held: Vec::new() }).collect(), acceptor)
+}
+
+/// Waits for an event to be reported on the given substream, others being
+/// reported as well when the bonds are descheduled for a while.
+fn reported(events: &mpsc::Receiver<Option<usize>>, substream: usize) {
+    while events.recv_timeout(Duration::from_secs(10)).unwrap() != Some(substream) {}
+}
+
+#[test]
+fn frames_sent_on_silent_substreams_fail_the_bond() {
+    let silent = Arc::new(AtomicBool::new(false));
+    // Only the path of the second substream from the connecting side goes silent.
+    let (connector, acceptor) = silent_paths(&[Arc::new(AtomicBool::new(false)), silent.clone()]);
+    let events = Arc::new(Mutex::new(Vec::<BondEvent>::new()));
+    let recorded = events.clone();
+    // Heartbeats asked for by one side are sent by both.
+    let config = BondConfig { heartbeat: heartbeat(20), observer: Some(Observer::new(move |e| recorded.lock().unwrap().push(e.clone()))), ..Default::default() };
+    let handle = std::thread::spawn(move || BondStream::from_streams(connector, Role::Connector).unwrap());
+    let mut server = BondStream::from_streams_with(acceptor, Role::Acceptor, &config).unwrap();
+    let mut client = handle.join().unwrap();
+    silent.store(true, Ordering::Relaxed);
+    // The second of the three frames is lost on the silent path, while the
+    // client keeps sending heartbeats as it waits for an answer.
+    let handle = std::thread::spawn(move || {
+        client.write_all(&payload(20_000)).unwrap();
+        let _ = client.read(&mut [0u8; 1]);
+    });
+    let err = server.read_exact(&mut [0u8; 20_000]).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+    assert!(err.to_string().contains("frame 1 can only have been sent on substream 1"), "unexpected error: {err}");
+    assert!(matches!(BondError::of(&err), Some(BondError::Timeout { substream: Some(1), .. })), "unexpected error: {err:?}");
+    drop(server);
+    handle.join().unwrap();
+    let kinds: Vec<_> = events.lock().unwrap().iter().filter(|e| e.substream == Some(1)).map(|e| e.kind.clone()).collect();
+    assert_eq!(kinds[0], BondEventKind::SubstreamStalled);
+    assert!(matches!(kinds[1], BondEventKind::SubstreamFailed { .. }), "{kinds:?}");
+}
+
+#[test]
+fn traffic_keeps_flowing_when_a_substream_goes_silent() {
+    let silent = Arc::new(AtomicBool::new(false));
+    let (connector, acceptor) = silent_paths(&[Arc::new(AtomicBool::new(false)), silent.clone()]);
+    let (failures, failed) = mpsc::channel();
+    let observer = Observer::new(move |e| {
+        if matches!(e.kind, BondEventKind::SubstreamFailed { .. }) {
+            let _ = failures.send(e.substream);
+        }
+    });
+    let config = BondConfig { heartbeat: heartbeat(10), io_mode: IoMode::Background { queue_size: 64 * 1024 }, ..Default::default() };
+    let client = BondConfig { observer: Some(observer), ..config.clone() };
+    let handle = std::thread::spawn(move || BondStream::from_streams_with(connector, Role::Connector, &client).unwrap());
+    let mut server = BondStream::from_streams_with(acceptor, Role::Acceptor, &config).unwrap();
+    let client = send(handle.join().unwrap(), &mut server, &payload(100_000));
+    // The path of the second substream from the client goes silent while the
+    // bond is idle: the server gives up on it and no longer sends anything on
+    // it, thus the client gives up on it as well.
+    silent.store(true, Ordering::Relaxed);
+    reported(&failed, 1);
+    let mut client = send(client, &mut server, &payload(100_000));
+    send(server, &mut client, &payload(100_000));
+}
+
+#[test]
+fn frames_move_off_a_path_that_died_before_taking_them() {
+    let (client, mut server, budget) = dying_bond();
+    *budget.lock().unwrap() = Some(0);
+    // The frames scheduled on the second substream block until the client
+    // gives up on it, and go on the first one instead.
+    let mut client = send(client, &mut server, &payload(1 << 20));
+    send(server, &mut client, &payload(100_000));
+}
+
+#[test]
+fn paths_dying_in_the_middle_of_a_frame_fail_the_bond() {
+    let (mut client, mut server, budget) = dying_bond();
+    // Beyond any heartbeat, and within the first frame on the second substream.
+    *budget.lock().unwrap() = Some(1000);
+    let handle = std::thread::spawn(move || {
+        let _ = server.read_to_end(&mut Vec::new());
+    });
+    let err = client.write_all(&payload(1 << 20)).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+    assert!(matches!(BondError::of(&err), Some(BondError::Timeout { substream: Some(1), .. })), "unexpected error: {err:?}");
+    drop(client);
+    handle.join().unwrap();
+}
+
+#[test]
+fn idle_peers_are_not_mistaken_for_silent_paths() {
+    let config = BondConfig { heartbeat: heartbeat(10), ..Default::default() };
+    let (client, mut server) = tcp_bond_with(3, &config, &BondConfig::default());
+    // The client does not drive its bond while it sleeps, the server waits
+    // on substreams none of which is heard from.
+    let handle = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(150));
+        client
+    });
+    let client = send(handle.join().unwrap(), &mut server, &payload(100_000));
+    send(server, &mut { client }, &payload(100_000));
+}
+
+#[test]
+fn idle_background_bonds_exchange_heartbeats() {
+    let silent = Arc::new(AtomicBool::new(false));
+    let (connector, acceptor) = silent_paths(&[Arc::new(AtomicBool::new(false)), Arc::new(AtomicBool::new(false)), silent.clone()]);
+    let (stalls, stalled) = mpsc::channel();
+    let observer = Observer::new(move |e| {
+        if e.kind == BondEventKind::SubstreamStalled {
+            let _ = stalls.send(e.substream);
+        }
+    });
+    let config = BondConfig {
+        io_mode: IoMode::Background { queue_size: 64 * 1024 },
+        checksum: Checksum::Crc32c,
+        psk: Some(PreSharedKey::new([5; 32])),
+        heartbeat: heartbeat(10),
+        ..Default::default()
+    };
+    let server = BondConfig { observer: Some(observer), ..config.clone() };
+    let handle = std::thread::spawn(move || BondStream::from_streams_with(connector, Role::Connector, &config).unwrap());
+    let mut server = BondStream::from_streams_with(acceptor, Role::Acceptor, &server).unwrap();
+    let client = send(handle.join().unwrap(), &mut server, &payload(100_000));
+    // Neither side reads nor writes, a path going silent is only noticed
+    // because heartbeats keep coming on the others.
+    silent.store(true, Ordering::Relaxed);
+    reported(&stalled, 2);
+    // The heartbeats held back meanwhile go through once the path resumes.
+    silent.store(false, Ordering::Relaxed);
+    let client = send(client, &mut server, &payload(100_000));
+    send(server, &mut { client }, &payload(100_000));
+}
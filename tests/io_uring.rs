@@ -0,0 +1,80 @@
+//! Bonds whose substream I/O goes through io_uring.
+#![cfg(all(target_os = "linux", feature = "io-uring"))]
+
+mod common;
+
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+use bond_tcp::{Backend, BondConfig, BondError, BondEventKind, Observer, Scheduler};
+
+use common::{background, payload, send, tcp_bond_with};
+
+fn uring(config: BondConfig) -> BondConfig {
+    BondConfig { backend: Backend::IoUring, ..config }
+}
+
+#[test]
+fn inline_round_trip() {
+    let config = uring(BondConfig::default());
+    let (client, mut server) = tcp_bond_with(4, &config, &config);
+    let data = payload(2 << 20);
+    let mut client = send(client, &mut server, &data);
+    send(server, &mut client, &data);
+}
+
+#[test]
+fn background_round_trip() {
+    let config = uring(background(16 * 1024));
+    let (client, mut server) = tcp_bond_with(4, &config, &config);
+    let data = payload(2 << 20);
+    let mut client = send(client, &mut server, &data);
+    send(server, &mut client, &data);
+}
+
+#[test]
+fn numbered_frames_are_located_on_completion() {
+    let config = uring(BondConfig { scheduler: Scheduler::Latency, ping_interval: Some(std::time::Duration::from_millis(2)), ..Default::default() });
+    let (client, mut server) = tcp_bond_with(3, &config, &config);
+    let data = payload(2 << 20);
+    let mut client = send(client, &mut server, &data);
+    send(server, &mut client, &data);
+}
+
+#[test]
+fn io_uring_bonds_talk_to_polled_ones() {
+    let (client, mut server) = tcp_bond_with(3, &uring(BondConfig::default()), &BondConfig::default());
+    let data = payload(1 << 20);
+    let mut client = send(client, &mut server, &data);
+    send(server, &mut client, &data);
+}
+
+#[test]
+fn dropping_a_bond_with_reads_in_flight() {
+    let config = uring(background(8192));
+    for _ in 0..20 {
+        let (client, server) = tcp_bond_with(3, &config, &config);
+        drop(client);
+        drop(server);
+    }
+}
+
+#[test]
+fn failed_operations_fail_their_substream() {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let recorded = events.clone();
+    let observer = Observer::new(move |e| recorded.lock().unwrap().push(e.clone()));
+    let (mut client, server) = tcp_bond_with(2, &uring(BondConfig { observer: Some(observer), ..Default::default() }), &BondConfig::default());
+    drop(server);
+    let err = loop {
+        if let Err(e) = client.write_all(&[0u8; 64 * 1024]).and_then(|()| client.flush()) {
+            break e;
+        }
+    };
+    let Some(BondError::SubstreamFailed { index, .. }) = BondError::of(&err) else {
+        panic!("unexpected error: {err:?}");
+    };
+    let failure = events.lock().unwrap().last().cloned().unwrap();
+    assert_eq!(failure.kind, BondEventKind::SubstreamFailed { error: err.to_string() });
+    assert_eq!(failure.substream, Some(*index));
+}
@@ -0,0 +1,44 @@
+//! Bonds whose substreams leave from chosen local addresses.
+
+mod common;
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+use bond_tcp::{BondConfig, BondTcpListener, BondTcpStream};
+use common::{forwarder, payload, send};
+
+#[test]
+fn substreams_leave_from_the_requested_sources() {
+    let mut listener = BondTcpListener::bind("127.0.0.1:0", 5).unwrap();
+    let (relayed, sources) = forwarder(listener.local_addr().unwrap());
+    let locals: Vec<SocketAddr> = (2..5).map(|i| SocketAddr::new(Ipv4Addr::new(127, 0, 0, i).into(), 0)).collect();
+    let config = BondConfig { local_addrs: locals, ..Default::default() };
+    let handle = std::thread::spawn(move || BondTcpStream::connect_with(relayed, &config).unwrap());
+    let (mut server, _) = listener.accept().unwrap();
+    let client = handle.join().unwrap();
+    let mut ips: Vec<IpAddr> = sources.lock().unwrap().iter().map(SocketAddr::ip).collect();
+    ips.sort();
+    let expected: Vec<IpAddr> = [2, 2, 3, 3, 4].into_iter().map(|i| Ipv4Addr::new(127, 0, 0, i).into()).collect();
+    assert_eq!(ips, expected);
+    send(client, &mut server, &payload(200_000));
+}
+
+#[test]
+fn the_completing_substream_leaves_from_its_source() {
+    let mut listener = BondTcpListener::bind("127.0.0.1:0", 2).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let locals = vec!["127.0.0.2:0".parse().unwrap(), "127.0.0.3:0".parse().unwrap()];
+    let config = BondConfig { local_addrs: locals, ..Default::default() };
+    let handle = std::thread::spawn(move || BondTcpStream::connect_with(addr, &config).unwrap());
+    let (mut server, peer) = listener.accept().unwrap();
+    assert_eq!(peer.ip(), Ipv4Addr::new(127, 0, 0, 3));
+    send(handle.join().unwrap(), &mut server, &payload(10_000));
+}
+
+#[test]
+fn sources_must_match_the_family_of_the_endpoint() {
+    let listener = BondTcpListener::bind("127.0.0.1:0", 1).unwrap();
+    let config = BondConfig { local_addrs: vec!["[::1]:0".parse().unwrap()], ..Default::default() };
+    let err = BondTcpStream::connect_with(listener.local_addr().unwrap(), &config).map(|_| ()).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
@@ -0,0 +1,82 @@
+//! Metrics of bonds and listeners, rendered in the Prometheus text format.
+#![cfg(feature = "metrics")]
+
+mod common;
+
+use std::io::Write;
+
+use bond_tcp::{BondConfig, BondTcpListener, BondTcpStream, Metrics, PreSharedKey};
+use common::{payload, send, tcp_bond_with};
+
+/// Returns the value of the sample of `metrics` with the given name and
+/// labels, if any.
+fn sample(metrics: &Metrics, name: &str) -> Option<u64> {
+    metrics.render().lines().find_map(|l| l.strip_prefix(name)?.strip_prefix(' ')?.parse().ok())
+}
+
+#[test]
+fn bonds_are_counted_while_open() {
+    let metrics = Metrics::new();
+    let config = BondConfig { metrics: Some(metrics.clone()), ..Default::default() };
+    let (client, mut server) = tcp_bond_with(3, &config, &config);
+    assert_eq!(sample(&metrics, "bond_tcp_bonds_active"), Some(2));
+    assert_eq!(sample(&metrics, "bond_tcp_bonds_formed_total"), Some(2));
+    assert_eq!(sample(&metrics, "bond_tcp_bonds_pending"), Some(0));
+    let client = send(client, &mut server, &payload(100_000));
+    drop(server);
+    drop(client);
+    assert_eq!(sample(&metrics, "bond_tcp_bonds_active"), Some(0));
+    assert_eq!(sample(&metrics, "bond_tcp_bonds_formed_total"), Some(2));
+}
+
+#[test]
+fn substream_traffic_is_labelled_by_bond() {
+    let metrics = Metrics::new();
+    let config = BondConfig { metrics: Some(metrics.clone()), ..Default::default() };
+    let mut listener = BondTcpListener::bind_with("127.0.0.1:0", 2, config).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handle = std::thread::spawn(move || BondTcpStream::connect(addr).unwrap());
+    let (mut server, _) = listener.accept().unwrap();
+    let client = handle.join().unwrap();
+    let client = send(client, &mut server, &payload(100_000));
+    let stats = server.stats();
+    let rendered = metrics.render();
+    let cid = rendered
+        .lines()
+        .find_map(|l| l.strip_prefix("bond_tcp_reorder_buffer_bytes{bond=\"")?.split('"').next())
+        .expect("the bond is labelled")
+        .to_string();
+    for (i, s) in stats.substreams.iter().enumerate() {
+        let name = format!("bond_tcp_substream_received_bytes_total{{bond=\"{cid}\",substream=\"{i}\"}}");
+        assert_eq!(sample(&metrics, &name), Some(s.counters.bytes_received));
+        assert!(s.counters.bytes_received > 0);
+    }
+    drop(client);
+}
+
+#[test]
+fn failed_handshakes_and_half_formed_bonds_are_reported() {
+    let metrics = Metrics::new();
+    let server = BondConfig { metrics: Some(metrics.clone()), psk: Some(PreSharedKey::new([1; 32])), ..Default::default() };
+    let mut listener = BondTcpListener::bind_with("127.0.0.1:0", 2, server).unwrap();
+    let addr = listener.local_addr().unwrap();
+    // A connection without the key asked for by the listener fails the handshake.
+    let handle = std::thread::spawn(move || BondTcpStream::connect(addr).map(|_| ()));
+    assert!(listener.accept().is_err());
+    let _ = handle.join().unwrap();
+    assert_eq!(sample(&metrics, "bond_tcp_handshake_failures_total"), Some(1));
+    // The first connection of a bond leaves it half-formed until the others join.
+    let mut listener = BondTcpListener::bind_with("127.0.0.1:0", 2, BondConfig { metrics: Some(metrics.clone()), ..Default::default() }).unwrap();
+    let mut first = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let mut hello = uuid::Uuid::new_v4().to_bytes_le().to_vec();
+    hello.push(0);
+    first.write_all(&hello).unwrap();
+    let accept = std::thread::spawn(move || {
+        let _ = listener.accept();
+    });
+    while sample(&metrics, "bond_tcp_bonds_pending") != Some(1) {
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    drop(first);
+    drop(accept);
+}
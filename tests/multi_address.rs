@@ -0,0 +1,42 @@
+//! Bonds whose substreams reach the listener through several addresses.
+
+mod common;
+
+use std::net::SocketAddr;
+
+use bond_tcp::{BondConfig, BondTcpListener, BondTcpStream};
+use common::{forwarder, payload, send};
+
+#[test]
+fn substreams_are_spread_over_the_endpoints() {
+    let mut listener = BondTcpListener::bind_multi(&["127.0.0.1:0", "127.0.0.2:0"], 5, BondConfig::default()).unwrap();
+    let addrs = listener.local_addrs().unwrap();
+    assert_eq!(addrs.len(), 2);
+    let (relayed, sources) = forwarder(addrs[1]);
+    let handle = std::thread::spawn(move || BondTcpStream::connect_multi(&[addrs[0], relayed], &BondConfig::default()).unwrap());
+    let (mut server, _) = listener.accept().unwrap();
+    let mut client = handle.join().unwrap();
+    assert_eq!(sources.lock().unwrap().len(), 2);
+    let data = payload(500_000);
+    client = send(client, &mut server, &data);
+    send(server, &mut client, &data);
+}
+
+#[test]
+fn bonds_are_matched_across_the_listening_addresses() {
+    let mut listener = BondTcpListener::bind_multi(&["127.0.0.1:0", "127.0.0.2:0", "127.0.0.3:0"], 3, BondConfig::default()).unwrap();
+    let mut addrs = listener.local_addrs().unwrap();
+    // Every connection lands on a different address, starting with the last.
+    addrs.reverse();
+    let handle = std::thread::spawn(move || BondTcpStream::connect_multi(&addrs, &BondConfig::default()).unwrap());
+    let (mut server, peer) = listener.accept().unwrap();
+    assert_eq!(peer.ip(), std::net::Ipv4Addr::new(127, 0, 0, 1));
+    send(handle.join().unwrap(), &mut server, &payload(100_000));
+}
+
+#[test]
+fn a_bond_needs_an_endpoint() {
+    let endpoints: [SocketAddr; 0] = [];
+    let err = BondTcpStream::connect_multi(&endpoints, &BondConfig::default()).map(|_| ()).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
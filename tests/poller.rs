@@ -0,0 +1,99 @@
+//! The poller driving the substreams of a bond.
+
+mod common;
+
+#[cfg(target_os = "linux")]
+use std::os::fd::BorrowedFd;
+
+use bond_tcp::BondConfig;
+#[cfg(target_os = "linux")]
+use bond_tcp::{BondTcpListener, BondTcpStream};
+use common::{background, payload, send, tcp_bond, tcp_bond_with};
+#[cfg(target_os = "linux")]
+use socket2::SockRef;
+
+/// The socket buffers of the bonds made to wait on their substreams, far
+/// smaller than the payloads they exchange.
+#[cfg(target_os = "linux")]
+const TIGHT_BUFFER: usize = 64 * 1024;
+
+/// Bonds `n` TCP connections on the loopback interface like `tcp_bond_with`,
+/// with socket buffers that cannot hold the payloads sent, so that the
+/// writer has to wait until the reader catches up.
+#[cfg(target_os = "linux")]
+fn tight_bond_with(n: u8, client: &BondConfig, server: &BondConfig) -> (BondTcpStream, BondTcpStream) {
+    let mut listener = BondTcpListener::bind_with("127.0.0.1:0", n, server.clone()).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let client = client.clone();
+    let handle = std::thread::spawn(move || BondTcpStream::connect_with(addr, &client).unwrap());
+    let (server, _) = listener.accept().unwrap();
+    let client = handle.join().unwrap();
+    for entry in std::fs::read_dir("/proc/self/fd").unwrap() {
+        let Ok(fd) = entry.unwrap().file_name().to_string_lossy().parse() else {
+            continue;
+        };
+        // SAFETY: the descriptor is only queried, the socket options of one
+        // closed meanwhile failing to be set.
+        let fd = unsafe { BorrowedFd::borrow_raw(fd) };
+        let socket = SockRef::from(&fd);
+        // Both ends of the substreams, accepted on the port of the listener
+        // or connected to it.
+        let ports = [socket.local_addr(), socket.peer_addr()].map(|a| a.ok().and_then(|a| a.as_socket()).map(|a| a.port()));
+        if ports.contains(&Some(addr.port())) {
+            let _ = socket.set_send_buffer_size(TIGHT_BUFFER);
+            let _ = socket.set_recv_buffer_size(TIGHT_BUFFER);
+        }
+    }
+    (client, server)
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn edge_triggered_substreams_are_never_re_armed() {
+    for config in [BondConfig::default(), background(64 * 1024)] {
+        let (client, mut server) = tcp_bond_with(3, &config, &config);
+        let data = payload(4 << 20);
+        let mut client = send(client, &mut server, &data);
+        let server = send(server, &mut client, &data);
+        for counters in [client.io_counters(), server.io_counters()] {
+            assert_eq!(counters.rearms, 0, "{counters:?}");
+            assert!(counters.reads > 0 && counters.writes > 0, "{counters:?}");
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn oneshot_substreams_are_re_armed_before_every_wait() {
+    let inline = BondConfig { oneshot: true, ..Default::default() };
+    let background = BondConfig { oneshot: true, ..background(64 * 1024) };
+    for config in [inline, background] {
+        let (client, mut server) = tight_bond_with(3, &config, &config);
+        let data = payload(4 << 20);
+        let mut client = send(client, &mut server, &data);
+        let server = send(server, &mut client, &data);
+        for counters in [client.io_counters(), server.io_counters()] {
+            assert!(counters.polls > 0 && counters.rearms >= counters.polls, "{counters:?}");
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn the_poll_mode_is_not_negotiated() {
+    let oneshot = BondConfig { oneshot: true, ..Default::default() };
+    let (client, mut server) = tight_bond_with(3, &oneshot, &BondConfig::default());
+    let data = payload(4 << 20);
+    let mut client = send(client, &mut server, &data);
+    let server = send(server, &mut client, &data);
+    assert!(client.io_counters().rearms > 0, "{:?}", client.io_counters());
+    assert_eq!(server.io_counters().rearms, 0, "{:?}", server.io_counters());
+}
+
+#[test]
+fn counters_start_at_zero() {
+    let (client, server) = tcp_bond(2);
+    for counters in [client.io_counters(), server.io_counters()] {
+        assert_eq!(counters.rearms, 0, "{counters:?}");
+    }
+}
@@ -0,0 +1,76 @@
+//! Sending copies of frames on several substreams.
+
+mod common;
+
+use std::io::{ErrorKind, Read, Write};
+use std::time::{Duration, Instant};
+
+use bond_tcp::BondConfig;
+use common::{background, payload, send, skewed_bond, tcp_bond, tcp_bond_with};
+
+#[test]
+fn the_first_copy_is_delivered_and_the_others_discarded() {
+    let client = BondConfig { redundancy: 3, ..background(64 * 1024) };
+    let (client, mut server) = tcp_bond_with(3, &client, &BondConfig::default());
+    let mut client = send(client, &mut server, &payload(1 << 20));
+    let sent = client.stats().totals();
+    let received = server.stats().totals();
+    assert_eq!(sent.frames_sent, 3 * (received.frames_received - received.duplicates_received));
+    assert!(received.duplicates_received > 0, "{received:?}");
+    // The accepting side numbers its frames as well, but sends them once.
+    send(server, &mut client, &payload(1 << 20));
+    assert_eq!(client.stats().totals().duplicates_received, 0);
+}
+
+#[test]
+fn redundant_writes_are_not_held_up_by_a_slow_path() {
+    let delay = Duration::from_millis(200);
+    let client = BondConfig { redundancy: 1, ..Default::default() };
+    let (mut client, mut server, _) = skewed_bond(delay, &client, &BondConfig::default());
+    let message = payload(100);
+    let mut received = vec![0u8; message.len()];
+    // Frames sent once take turns on both paths.
+    let slowest = (0..2)
+        .map(|_| {
+            let start = Instant::now();
+            client.write_all(&message).unwrap();
+            server.read_exact(&mut received).unwrap();
+            start.elapsed()
+        })
+        .max()
+        .unwrap();
+    assert!(slowest >= delay, "{slowest:?}");
+    for _ in 0..10 {
+        let start = Instant::now();
+        assert_eq!(client.write_redundant(&message, 2).unwrap(), message.len());
+        server.read_exact(&mut received).unwrap();
+        assert!(received == message);
+        assert!(start.elapsed() < delay / 2, "{:?}", start.elapsed());
+    }
+    let client = send(client, &mut server, &payload(100_000));
+    send(server, &mut { client }, &payload(100_000));
+}
+
+#[test]
+fn background_writes_keep_their_number_of_copies() {
+    let (mut client, mut server) = tcp_bond_with(2, &BondConfig { redundancy: 1, ..background(64 * 1024) }, &BondConfig::default());
+    let message = payload(100);
+    client.write_redundant(&message, 2).unwrap();
+    client.write_all(&payload(20_000)).unwrap();
+    client.flush().unwrap();
+    let mut received = vec![0u8; message.len() + 20_000];
+    server.read_exact(&mut received).unwrap();
+    // Two copies of the message, then three fragments sent once.
+    assert_eq!(client.stats().totals().frames_sent, 2 + 3);
+}
+
+#[test]
+fn copies_need_numbered_frames() {
+    let (mut client, mut server) = tcp_bond(2);
+    let e = client.write_redundant(b"copied", 2).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::Unsupported);
+    assert_eq!(client.write_redundant(b"once", 1).unwrap(), 4);
+    let mut received = [0u8; 4];
+    server.read_exact(&mut received).unwrap();
+    assert_eq!(&received, b"once");
+}
@@ -0,0 +1,43 @@
+//! Round-trip times of the substreams, measured with pings.
+
+mod common;
+
+use std::time::Duration;
+
+use bond_tcp::{BondConfig, Checksum, IoMode, PreSharedKey};
+use common::{background, payload, send, tcp_bond_with};
+
+#[test]
+fn pings_measure_the_round_trip_time_of_every_substream() {
+    let server = BondConfig { checksum: Checksum::Crc32c, psk: Some(PreSharedKey::new([9; 32])), ..background(64 * 1024) };
+    let client = BondConfig { ping_interval: Some(Duration::from_millis(10)), ..server.clone() };
+    let (client, mut server) = tcp_bond_with(3, &client, &server);
+    std::thread::sleep(Duration::from_millis(100));
+    for s in client.stats().substreams.iter() {
+        let rtt = s.rtt.expect("pings were answered");
+        assert!(rtt.samples >= 2, "{rtt:?}");
+        assert!(rtt.min <= rtt.latest && rtt.min <= rtt.smoothed, "{rtt:?}");
+        assert!(rtt.smoothed < Duration::from_millis(100), "{rtt:?}");
+    }
+    // Only the side asking for pings sends them.
+    assert!(server.stats().substreams.iter().all(|s| s.rtt.is_none()));
+    let client = send(client, &mut server, &payload(100_000));
+    send(server, &mut { client }, &payload(100_000));
+}
+
+#[test]
+fn inline_bonds_measure_while_they_are_driven() {
+    let client = BondConfig { io_mode: IoMode::Inline, ping_interval: Some(Duration::from_millis(5)), ..Default::default() };
+    let (mut client, server) = tcp_bond_with(2, &client, &background(64 * 1024));
+    let mut server = Some(server);
+    // Pongs are read whenever the client waits for its substreams.
+    for _ in 0..50 {
+        if client.stats().substreams.iter().all(|s| s.rtt.is_some()) {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(5));
+        server = Some(send(server.take().unwrap(), &mut client, &payload(100_000)));
+    }
+    assert!(client.stats().substreams.iter().all(|s| s.rtt.is_some_and(|r| r.samples > 0)));
+    send(client, &mut server.unwrap(), &payload(100_000));
+}
@@ -0,0 +1,48 @@
+//! Scheduling frames by latency over paths of different round-trip times.
+
+mod common;
+
+use std::io::{Read, Write};
+use std::time::Duration;
+
+use bond_tcp::{BondConfig, IoMode, Scheduler};
+use common::{payload, send, skewed_bond};
+
+#[test]
+fn frames_take_the_path_they_arrive_first_on() {
+    let config = BondConfig {
+        io_mode: IoMode::Background { queue_size: 64 * 1024 },
+        scheduler: Scheduler::Latency,
+        ping_interval: Some(Duration::from_millis(10)),
+        ..Default::default()
+    };
+    let (mut client, mut server, slow) = skewed_bond(Duration::from_millis(20), &config, &config);
+    // Both paths are measured before the first message.
+    while client.stats().substreams.iter().any(|s| s.rtt.is_none()) {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    let rtts: Vec<_> = client.stats().substreams.iter().map(|s| s.rtt.unwrap().smoothed).collect();
+    assert!(rtts[slow] > rtts[1 - slow] + Duration::from_millis(20), "{rtts:?}");
+    let message = payload(1000);
+    let mut received = vec![0u8; message.len()];
+    for _ in 0..50 {
+        client.write_all(&message).unwrap();
+        client.flush().unwrap();
+        server.read_exact(&mut received).unwrap();
+        assert!(received == message);
+    }
+    let sent: Vec<_> = client.stats().substreams.iter().map(|s| s.counters.frames_sent).collect();
+    assert!(sent[slow] * 10 < sent[1 - slow], "{sent:?}");
+    send(server, &mut client, &payload(100_000));
+}
+
+#[test]
+fn numbered_frames_are_received_in_order_over_skewed_paths() {
+    let latency = BondConfig { scheduler: Scheduler::Latency, ping_interval: Some(Duration::from_millis(5)), ..Default::default() };
+    // The accepting side schedules its frames round-robin, but numbers them
+    // as the connecting side asks.
+    let (client, mut server, _) = skewed_bond(Duration::from_millis(5), &latency, &BondConfig::default());
+    let data = payload(2 << 20);
+    let mut client = send(client, &mut server, &data);
+    send(server, &mut client, &data);
+}
@@ -0,0 +1,135 @@
+//! Socket options set on the substreams of a bond.
+
+mod common;
+
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::os::fd::BorrowedFd;
+use std::time::Duration;
+
+use bond_tcp::{BondConfig, BondStream, BondTcpListener, BondTcpStream, Keepalive, Role, SocketOptions};
+use common::{payload, send};
+use socket2::SockRef;
+
+/// Returns `n` connected pairs of TCP connections on the loopback interface,
+/// split into the ends of each side.
+fn tcp_pairs(n: usize) -> (Vec<TcpStream>, Vec<TcpStream>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    (0..n).map(|_| (TcpStream::connect(addr).unwrap(), listener.accept().unwrap().0)).unzip()
+}
+
+/// Returns the receive buffer sizes of the descriptors of the process
+/// connected from the given addresses, e.g. the substreams accepted from a
+/// bond, along with their peer.
+fn recv_buffers_of_peers(peers: &[SocketAddr]) -> Vec<(SocketAddr, usize)> {
+    let mut sizes = Vec::new();
+    for entry in std::fs::read_dir("/proc/self/fd").unwrap() {
+        let Ok(fd) = entry.unwrap().file_name().to_string_lossy().parse() else {
+            continue;
+        };
+        // SAFETY: the descriptor is only queried, the socket options of one
+        // closed meanwhile failing or belonging to another peer.
+        let fd = unsafe { BorrowedFd::borrow_raw(fd) };
+        let socket = SockRef::from(&fd);
+        if let Some(peer) = socket.peer_addr().ok().and_then(|a| a.as_socket())
+            && peers.contains(&peer)
+        {
+            sizes.push((peer, socket.recv_buffer_size().unwrap()));
+        }
+    }
+    sizes
+}
+
+/// Returns the congestion control algorithm of a socket.
+fn congestion(socket: &SockRef) -> String {
+    let name = socket.tcp_congestion().unwrap();
+    String::from_utf8(name.into_iter().take_while(|&b| b != 0).collect()).unwrap()
+}
+
+#[test]
+fn options_are_set_on_both_sides() {
+    let config = BondConfig {
+        socket_options: vec![
+            SocketOptions {
+                congestion: Some("reno".into()),
+                notsent_lowat: Some(16 * 1024),
+                tos: Some(0x20),
+                keepalive: Some(Keepalive { time: Duration::from_secs(30), interval: Some(Duration::from_secs(5)), retries: Some(4) }),
+                ..Default::default()
+            },
+            SocketOptions { congestion: Some("cubic".into()), send_buffer_size: Some(64 * 1024), ..Default::default() },
+        ],
+        ..Default::default()
+    };
+    let (connector, acceptor) = tcp_pairs(3);
+    let sockets: Vec<TcpStream> = connector.iter().chain(acceptor.iter()).map(|s| s.try_clone().unwrap()).collect();
+    let client_config = config.clone();
+    let handle = std::thread::spawn(move || BondStream::from_streams_with(connector, Role::Connector, &client_config).unwrap());
+    let mut server = BondStream::from_streams_with(acceptor, Role::Acceptor, &config).unwrap();
+    let client = handle.join().unwrap();
+    for (i, socket) in sockets.iter().enumerate() {
+        let socket = SockRef::from(socket);
+        let index = i % 3;
+        if index % 2 == 0 {
+            assert_eq!(congestion(&socket), "reno");
+            assert_eq!(socket.tcp_notsent_lowat().unwrap(), 16 * 1024);
+            assert_eq!(socket.tos_v4().unwrap(), 0x20);
+            assert!(socket.keepalive().unwrap());
+            assert_eq!(socket.tcp_keepalive_time().unwrap(), Duration::from_secs(30));
+            assert_eq!(socket.tcp_keepalive_interval().unwrap(), Duration::from_secs(5));
+            assert_eq!(socket.tcp_keepalive_retries().unwrap(), 4);
+        } else {
+            assert_eq!(congestion(&socket), "cubic");
+            // The system doubles the size asked for, for its bookkeeping.
+            assert!(socket.send_buffer_size().unwrap() >= 64 * 1024);
+            assert!(!socket.keepalive().unwrap());
+        }
+    }
+    send(client, &mut server, &payload(100_000));
+}
+
+#[test]
+fn connecting_fails_on_options_the_system_rejects() {
+    let listener = BondTcpListener::bind("127.0.0.1:0", 1).unwrap();
+    let config = BondConfig {
+        socket_options: vec![SocketOptions { congestion: Some("no-such-algorithm".into()), ..Default::default() }],
+        ..Default::default()
+    };
+    assert!(BondTcpStream::connect_with(listener.local_addr().unwrap(), &config).is_err());
+}
+
+#[test]
+fn accepting_fails_on_options_the_system_rejects() {
+    let config = BondConfig {
+        socket_options: vec![SocketOptions::default(), SocketOptions { congestion: Some("no-such-algorithm".into()), ..Default::default() }],
+        ..Default::default()
+    };
+    let mut listener = BondTcpListener::bind_with("127.0.0.1:0", 2, config).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handle = std::thread::spawn(move || BondTcpStream::connect(addr));
+    assert!(listener.accept().is_err());
+    drop(listener);
+    let _ = handle.join().unwrap();
+}
+
+#[test]
+fn accepted_substreams_get_their_receive_buffer_before_the_handshake() {
+    // Below the default limit of the system, above its default size.
+    let size = 200_000;
+    let config = BondConfig {
+        socket_options: vec![SocketOptions::default(), SocketOptions { recv_buffer_size: Some(size), ..Default::default() }],
+        ..Default::default()
+    };
+    let mut listener = BondTcpListener::bind_with("127.0.0.1:0", 2, config).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handle = std::thread::spawn(move || BondTcpStream::connect(addr).unwrap());
+    let (mut server, _) = listener.accept().unwrap();
+    let client = handle.join().unwrap();
+    // The first substream has no options of its own, and keeps the buffer of
+    // the listener it was accepted from.
+    let peers = client.substream_local_addrs().unwrap();
+    let sizes = recv_buffers_of_peers(&peers);
+    assert!(peers.iter().all(|p| sizes.iter().any(|(peer, _)| peer == p)), "{sizes:?}");
+    assert!(sizes.iter().all(|(_, s)| *s >= size), "{sizes:?}");
+    send(client, &mut server, &payload(100_000));
+}
@@ -0,0 +1,75 @@
+//! Spans under which bonds are traced.
+
+mod common;
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+
+use bond_tcp::{BondConfig, BondEventKind, BondTcpListener, BondTcpStream, Observer};
+use common::{payload, send};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::Subscriber;
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::Layer;
+
+/// Records the fields of the `bond` spans, keyed by their name.
+#[derive(Clone, Default)]
+struct Spans {
+    ids: Arc<Mutex<Vec<Id>>>,
+    fields: Arc<Mutex<HashMap<(Id, &'static str), String>>>,
+}
+
+struct Fields<'a>(&'a Spans, &'a Id);
+
+impl Visit for Fields<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.0.fields.lock().unwrap().insert((self.1.clone(), field.name()), format!("{value:?}"));
+    }
+}
+
+impl<S: Subscriber> Layer<S> for Spans {
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, _: Context<'_, S>) {
+        if attrs.metadata().name() == "bond" {
+            self.ids.lock().unwrap().push(id.clone());
+            attrs.record(&mut Fields(self, id));
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, _: Context<'_, S>) {
+        if self.ids.lock().unwrap().contains(id) {
+            values.record(&mut Fields(self, id));
+        }
+    }
+}
+
+#[test]
+fn bond_spans_carry_the_identifier_and_the_peer() {
+    let spans = Spans::default();
+    let cid = Arc::new(Mutex::new(None));
+    let formed = cid.clone();
+    let observer = Observer::new(move |e| {
+        if e.kind == BondEventKind::BondFormed {
+            *formed.lock().unwrap() = e.cid;
+        }
+    });
+    let mut listener = BondTcpListener::bind_with("127.0.0.1:0", 2, BondConfig { observer: Some(observer), ..Default::default() }).unwrap();
+    let addr = listener.local_addr().unwrap();
+    // Both sides open their spans at the same callsite, the subscriber is
+    // thus the global one, the connecting side running in another thread.
+    tracing::subscriber::set_global_default(tracing_subscriber::registry().with(spans.clone())).unwrap();
+    let handle = std::thread::spawn(move || BondTcpStream::connect(addr).unwrap());
+    let (mut server, _) = listener.accept().unwrap();
+    let peer = server.peer_addr().unwrap();
+    send(handle.join().unwrap(), &mut server, &payload(10_000));
+    let cid = cid.lock().unwrap().expect("the bond was formed");
+    let fields = spans.fields.lock().unwrap();
+    let mut recorded: Vec<_> = spans.ids.lock().unwrap().iter().map(|id| (fields[&(id.clone(), "cid")].clone(), fields[&(id.clone(), "peer")].clone())).collect();
+    recorded.sort();
+    // The span of either side carries the identifier of the bond and the
+    // address of the other side.
+    let mut expected = vec![(cid.to_string(), peer.to_string()), (cid.to_string(), addr.to_string())];
+    expected.sort();
+    assert_eq!(recorded, expected);
+}
@@ -0,0 +1,53 @@
+//! Statistics of bonds and of their substreams.
+
+mod common;
+
+use std::os::unix::net::UnixStream;
+
+use bond_tcp::{BondConfig, BondStream, Role};
+use common::{background, payload, send, tcp_bond_with};
+
+#[test]
+fn frames_are_accounted_to_their_substream() {
+    for config in [BondConfig::default(), background(64 * 1024)] {
+        let (client, mut server) = tcp_bond_with(4, &config, &config);
+        // 128 fragments, spread round-robin.
+        let client = send(client, &mut server, &payload(1 << 20));
+        let sent = client.stats();
+        let received = server.stats();
+        assert_eq!(sent.substreams.len(), 4);
+        assert_eq!(sent.totals().frames_sent, 128);
+        assert_eq!(received.totals().frames_received, 128);
+        for (tx, rx) in sent.substreams.iter().zip(received.substreams.iter()) {
+            assert_eq!(tx.counters.frames_sent, 32);
+            assert_eq!(tx.counters.bytes_sent, rx.counters.bytes_received);
+            assert_eq!(tx.counters.frames_received, 0);
+        }
+        assert_eq!(sent.totals().bytes_sent, sent.io.wire_bytes);
+        assert_eq!(received.io, server.io_counters());
+        assert!(!received.wait_time.is_zero());
+    }
+}
+
+#[test]
+fn tcp_substreams_report_their_connection_state() {
+    let (client, mut server) = tcp_bond_with(2, &BondConfig::default(), &BondConfig::default());
+    send(client, &mut server, &payload(100_000));
+    for s in server.stats().substreams {
+        let tcp = s.tcp.expect("TCP_INFO is reported on Linux");
+        assert!(tcp.cwnd > 0 && tcp.mss > 0);
+        assert!(!tcp.rtt.is_zero());
+    }
+}
+
+#[test]
+fn other_transports_have_no_tcp_state() {
+    let (connector, acceptor): (Vec<UnixStream>, Vec<UnixStream>) = (0..2).map(|_| UnixStream::pair().unwrap()).unzip();
+    let handle = std::thread::spawn(move || BondStream::from_streams(acceptor, Role::Acceptor).unwrap());
+    let client = BondStream::from_streams(connector, Role::Connector).unwrap();
+    let mut server = handle.join().unwrap();
+    let client = send(client, &mut server, &payload(20_000));
+    let stats = client.stats();
+    assert_eq!(stats.totals().frames_sent, 3);
+    assert!(stats.substreams.iter().all(|s| s.tcp.is_none()));
+}
@@ -0,0 +1,146 @@
+//! Substreams secured by TLS, with locally generated self-signed
+//! certificates.
+#![cfg(feature = "rustls")]
+
+mod common;
+
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use bond_tcp::{BondConfig, BondTcpListener, BondTcpStream};
+use common::{background, payload, send};
+use rustls::client::{ClientSessionMemoryCache, ClientSessionStore, Tls12ClientSessionValue, Tls13ClientSessionValue};
+use rustls::pki_types::{CertificateDer, PrivatePkcs8KeyDer, ServerName};
+use rustls::{ClientConfig, NamedGroup, RootCertStore, ServerConfig};
+
+/// Returns the configuration of a server holding a self-signed certificate
+/// for `localhost`, along with the certificate.
+fn server_config() -> (Arc<ServerConfig>, CertificateDer<'static>) {
+    let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    let cert = certified.cert.der().clone();
+    let key = PrivatePkcs8KeyDer::from(certified.key_pair.serialize_der());
+    let config = ServerConfig::builder().with_no_client_auth().with_single_cert(vec![cert.clone()], key.into()).unwrap();
+    (Arc::new(config), cert)
+}
+
+/// Returns the configuration of a client trusting the given certificate.
+fn client_config(cert: CertificateDer<'static>) -> ClientConfig {
+    let mut roots = RootCertStore::empty();
+    roots.add(cert).unwrap();
+    ClientConfig::builder().with_root_certificates(roots).with_no_client_auth()
+}
+
+/// Bonds `n` TLS connections on the loopback interface, returns the
+/// connecting side and the accepting one.
+fn tls_bond(n: u8, config: &BondConfig, server: Arc<ServerConfig>, client: Arc<ClientConfig>) -> (BondTcpStream, BondTcpStream) {
+    let mut listener = BondTcpListener::bind_tls("127.0.0.1:0", n, config.clone(), server).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let config = config.clone();
+    let handle = std::thread::spawn(move || BondTcpStream::connect_tls(addr, ServerName::try_from("localhost").unwrap(), &config, client).unwrap());
+    let (server, _) = listener.accept().unwrap();
+    (handle.join().unwrap(), server)
+}
+
+/// Session storage counting the TLS 1.3 tickets used to resume sessions.
+#[derive(Debug)]
+struct CountingStore {
+    inner: ClientSessionMemoryCache,
+    resumed: AtomicUsize,
+}
+
+impl ClientSessionStore for CountingStore {
+    fn set_kx_hint(&self, server_name: ServerName<'static>, group: NamedGroup) {
+        self.inner.set_kx_hint(server_name, group)
+    }
+
+    fn kx_hint(&self, server_name: &ServerName<'_>) -> Option<NamedGroup> {
+        self.inner.kx_hint(server_name)
+    }
+
+    fn set_tls12_session(&self, server_name: ServerName<'static>, value: Tls12ClientSessionValue) {
+        self.inner.set_tls12_session(server_name, value)
+    }
+
+    fn tls12_session(&self, server_name: &ServerName<'_>) -> Option<Tls12ClientSessionValue> {
+        self.inner.tls12_session(server_name)
+    }
+
+    fn remove_tls12_session(&self, server_name: &ServerName<'static>) {
+        self.inner.remove_tls12_session(server_name)
+    }
+
+    fn insert_tls13_ticket(&self, server_name: ServerName<'static>, value: Tls13ClientSessionValue) {
+        self.inner.insert_tls13_ticket(server_name, value)
+    }
+
+    fn take_tls13_ticket(&self, server_name: &ServerName<'static>) -> Option<Tls13ClientSessionValue> {
+        let ticket = self.inner.take_tls13_ticket(server_name);
+        if ticket.is_some() {
+            self.resumed.fetch_add(1, Ordering::Relaxed);
+        }
+        ticket
+    }
+}
+
+#[test]
+fn tls_substreams_round_trip() {
+    let (server, cert) = server_config();
+    let client = Arc::new(client_config(cert));
+    for config in [BondConfig::default(), background(64 * 1024)] {
+        let (client, mut server) = tls_bond(3, &config, server.clone(), client.clone());
+        let data = payload(1 << 20);
+        let mut client = send(client, &mut server, &data);
+        send(server, &mut client, &data);
+    }
+}
+
+#[test]
+fn joining_connections_resume_the_tls_session() {
+    let (server, cert) = server_config();
+    let store = Arc::new(CountingStore { inner: ClientSessionMemoryCache::new(16), resumed: AtomicUsize::new(0) });
+    let mut client = client_config(cert);
+    client.resumption = rustls::client::Resumption::store(store.clone());
+    let (client, mut server) = tls_bond(4, &BondConfig::default(), server, Arc::new(client));
+    assert_eq!(store.resumed.load(Ordering::Relaxed), 3);
+    send(client, &mut server, &payload(10_000));
+}
+
+#[test]
+fn reads_end_once_the_peer_drops_a_tls_bond() {
+    let (server, cert) = server_config();
+    let (mut client, mut server) = tls_bond(2, &BondConfig::default(), server, Arc::new(client_config(cert)));
+    let data = payload(123_457);
+    let sent = data.clone();
+    let handle = std::thread::spawn(move || client.write_all(&sent).unwrap());
+    let mut received = Vec::new();
+    server.read_to_end(&mut received).unwrap();
+    handle.join().unwrap();
+    assert!(received == data, "received {} bytes out of {}", received.len(), data.len());
+}
+
+#[test]
+fn untrusted_certificates_are_rejected() {
+    let (server, _) = server_config();
+    let (_, other) = server_config();
+    let mut listener = BondTcpListener::bind_tls("127.0.0.1:0", 2, BondConfig::default(), server).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let client = Arc::new(client_config(other));
+    let handle = std::thread::spawn(move || BondTcpStream::connect_tls(addr, ServerName::try_from("localhost").unwrap(), &BondConfig::default(), client).map(|_| ()));
+    assert!(listener.accept().is_err());
+    let err = handle.join().unwrap().unwrap_err();
+    assert!(err.to_string().contains("certificate"), "unexpected error: {err}");
+}
+
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+#[test]
+fn tls_substreams_are_not_driven_by_io_uring() {
+    let (server, cert) = server_config();
+    let config = BondConfig { backend: bond_tcp::Backend::IoUring, ..Default::default() };
+    let mut listener = BondTcpListener::bind_tls("127.0.0.1:0", 2, config.clone(), server).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let client = Arc::new(client_config(cert));
+    let handle = std::thread::spawn(move || BondTcpStream::connect_tls(addr, ServerName::try_from("localhost").unwrap(), &config, client).map(|_| ()));
+    assert_eq!(listener.accept().map(|_| ()).unwrap_err().kind(), std::io::ErrorKind::Unsupported);
+    assert_eq!(handle.join().unwrap().unwrap_err().kind(), std::io::ErrorKind::Unsupported);
+}
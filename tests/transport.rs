@@ -0,0 +1,146 @@
+//! Bonds made of transports other than TCP connections.
+
+mod common;
+
+use std::io::{IoSlice, Read, Write};
+use std::os::fd::{AsFd, BorrowedFd};
+use std::os::unix::net::UnixStream;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use bond_tcp::{BondConfig, BondStream, Checksum, Role, Scheduler, Transport};
+use common::{background, payload, send};
+
+/// Returns `n` connected pairs of Unix sockets, split into the ends of each
+/// side.
+fn socket_pairs(n: usize) -> (Vec<UnixStream>, Vec<UnixStream>) {
+    (0..n).map(|_| UnixStream::pair().unwrap()).unzip()
+}
+
+/// Bonds both ends of the given transports, returns the connecting side and
+/// the accepting one.
+fn bond<T: Transport>(connector: Vec<T>, acceptor: Vec<T>, config: &BondConfig) -> (BondStream<T>, BondStream<T>) {
+    let client = config.clone();
+    let handle = std::thread::spawn(move || BondStream::from_streams_with(connector, Role::Connector, &client).unwrap());
+    let server = BondStream::from_streams_with(acceptor, Role::Acceptor, config).unwrap();
+    (handle.join().unwrap(), server)
+}
+
+/// A Unix socket counting the bytes written to it.
+struct Counted {
+    socket: UnixStream,
+    written: Arc<AtomicUsize>,
+}
+
+impl Read for Counted {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.socket.read(buf)
+    }
+}
+
+impl Write for Counted {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.write_vectored(&[IoSlice::new(buf)])
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> std::io::Result<usize> {
+        let n = self.socket.write_vectored(bufs)?;
+        self.written.fetch_add(n, Ordering::Relaxed);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.socket.flush()
+    }
+}
+
+impl AsFd for Counted {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.socket.as_fd()
+    }
+}
+
+impl Transport for Counted {
+    fn set_nonblocking(&self, nonblocking: bool) -> std::io::Result<()> {
+        self.socket.set_nonblocking(nonblocking)
+    }
+}
+
+/// An in-memory pipe moving at most `chunk` bytes per call, whatever room
+/// or data there is, as a narrow byte stream would. It is backed by a Unix
+/// socket pair only for the readiness the bond polls for.
+struct Pipe {
+    socket: UnixStream,
+    chunk: usize,
+}
+
+impl Read for Pipe {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = buf.len().min(self.chunk);
+        self.socket.read(&mut buf[..len])
+    }
+}
+
+impl Write for Pipe {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.socket.write(&buf[..buf.len().min(self.chunk)])
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl AsFd for Pipe {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.socket.as_fd()
+    }
+}
+
+impl Transport for Pipe {
+    fn set_nonblocking(&self, nonblocking: bool) -> std::io::Result<()> {
+        self.socket.set_nonblocking(nonblocking)
+    }
+}
+
+#[test]
+fn unix_sockets_are_bonded() {
+    for config in [BondConfig::default(), BondConfig { checksum: Checksum::Crc32c, ..background(64 * 1024) }] {
+        let (connector, acceptor) = socket_pairs(3);
+        let (client, mut server) = bond(connector, acceptor, &config);
+        let data = payload(1 << 20);
+        let mut client = send(client, &mut server, &data);
+        send(server, &mut client, &data);
+    }
+}
+
+#[test]
+fn custom_transports_carry_the_frames() {
+    let written = Arc::new(AtomicUsize::new(0));
+    let (connector, acceptor) = socket_pairs(2);
+    let connector = connector.into_iter().map(|socket| Counted { socket, written: written.clone() }).collect();
+    let acceptor = acceptor.into_iter().map(|socket| Counted { socket, written: Arc::new(AtomicUsize::new(0)) }).collect();
+    let (client, mut server) = bond(connector, acceptor, &BondConfig::default());
+    let data = payload(100_000);
+    let client = send(client, &mut server, &data);
+    // The handshake and the frame headers come on top of the payload.
+    let wire = written.load(Ordering::Relaxed);
+    assert!(wire > data.len() && wire as u64 >= client.io_counters().wire_bytes, "{wire} bytes written");
+}
+
+#[test]
+fn pipes_carry_the_frames_a_few_bytes_at_a_time() {
+    let configs = [
+        BondConfig::default(),
+        BondConfig { checksum: Checksum::Crc32c, ..background(64 * 1024) },
+        BondConfig { scheduler: Scheduler::Latency, ..Default::default() },
+    ];
+    for config in configs {
+        let (connector, acceptor) = socket_pairs(3);
+        let pipes = |sockets: Vec<UnixStream>| sockets.into_iter().map(|socket| Pipe { socket, chunk: 7 }).collect();
+        let (client, mut server) = bond(pipes(connector), pipes(acceptor), &config);
+        let data = payload(100_000);
+        let mut client = send(client, &mut server, &data);
+        send(server, &mut client, &data);
+    }
+}
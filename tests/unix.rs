@@ -0,0 +1,60 @@
+//! Bonds of Unix domain socket connections.
+
+mod common;
+
+use std::os::unix::net::UnixListener;
+use std::path::PathBuf;
+
+use bond_tcp::{BondConfig, BondUnixListener, BondUnixStream, Checksum, PreSharedKey};
+use common::{background, payload, send};
+
+/// Returns a socket path unique to the test, removing any leftover of a
+/// previous run.
+fn socket_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("bond-tcp-{}-{name}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+/// Bonds `n` Unix domain socket connections, the connecting side with
+/// `client` and the accepting side with `server`.
+fn unix_bond_with(name: &str, n: u8, client: &BondConfig, server: &BondConfig) -> (BondUnixStream, BondUnixStream) {
+    let path = socket_path(name);
+    let mut listener = BondUnixListener::bind_with(&path, n, server.clone()).unwrap();
+    let client = client.clone();
+    let target = path.clone();
+    let handle = std::thread::spawn(move || BondUnixStream::connect_with(&target, &client).unwrap());
+    let (server, _) = listener.accept().unwrap();
+    let _ = std::fs::remove_file(&path);
+    (handle.join().unwrap(), server)
+}
+
+#[test]
+fn unix_bonds_round_trip() {
+    for (name, config) in [("inline", BondConfig::default()), ("background", background(64 * 1024))] {
+        let (client, mut server) = unix_bond_with(name, 4, &config, &config);
+        let data = payload(1 << 20);
+        let mut client = send(client, &mut server, &data);
+        send(server, &mut client, &data);
+    }
+}
+
+#[test]
+fn unix_bonds_negotiate_their_options() {
+    let client = BondConfig { checksum: Checksum::Crc32c, psk: Some(PreSharedKey::new([5; 32])), ..Default::default() };
+    let server = BondConfig { psk: Some(PreSharedKey::new([5; 32])), ..Default::default() };
+    let (client, mut server) = unix_bond_with("options", 2, &client, &server);
+    let mut client = send(client, &mut server, &payload(100_000));
+    send(server, &mut client, &payload(3));
+}
+
+#[test]
+fn listeners_bound_beforehand_accept_unix_bonds() {
+    let path = socket_path("from-std");
+    let mut listener = BondUnixListener::from_std(UnixListener::bind(&path).unwrap(), 1, BondConfig::default()).unwrap();
+    let target = path.clone();
+    let handle = std::thread::spawn(move || BondUnixStream::connect(&target).unwrap());
+    let (mut server, _) = listener.accept().unwrap();
+    let _ = std::fs::remove_file(&path);
+    send(handle.join().unwrap(), &mut server, &payload(10_000));
+}
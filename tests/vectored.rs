@@ -0,0 +1,70 @@
+//! Frames written along with their header, and the vectored I/O of bonds.
+
+mod common;
+
+use std::io::{IoSlice, IoSliceMut, Read, Write};
+use std::time::Duration;
+
+use common::{payload, tcp_bond};
+
+#[test]
+fn vectored_round_trip() {
+    let (mut client, mut server) = tcp_bond(2);
+    let (a, b) = (payload(20_000), payload(5));
+    let handle = std::thread::spawn(move || {
+        let n = client.write_vectored(&[IoSlice::new(&a), IoSlice::new(&b)]).unwrap();
+        client.write_all(&[a, b].concat()[n..]).unwrap();
+    });
+    let (mut a, mut b) = (vec![0u8; 20_000], vec![0u8; 5]);
+    let mut read = 0;
+    while read < a.len() + b.len() {
+        let mut bufs = [IoSliceMut::new(&mut a), IoSliceMut::new(&mut b)];
+        IoSliceMut::advance_slices(&mut &mut bufs[..], read);
+        let n = server.read_vectored(&mut bufs).unwrap();
+        assert!(n > 0);
+        read += n;
+    }
+    handle.join().unwrap();
+    assert!(a == payload(20_000) && b == payload(5));
+}
+
+#[test]
+fn frames_take_one_write_each() {
+    let (mut client, mut server) = tcp_bond(3);
+    // Two full fragments and a partial one, the socket buffers having room
+    // for all of them.
+    let data = payload(20_000);
+    client.write_all(&data).unwrap();
+    assert_eq!(client.io_counters().writes, 3);
+    let mut received = vec![0u8; data.len()];
+    server.read_exact(&mut received).unwrap();
+    assert!(received == data);
+}
+
+#[test]
+fn short_writes_resume_within_frames() {
+    let (mut client, mut server) = tcp_bond(3);
+    let data = payload(16 << 20);
+    let sent = data.clone();
+    let handle = std::thread::spawn(move || {
+        // Slices that do not line up with fragments.
+        let (a, b) = sent.split_at(12_345);
+        let (b, c) = b.split_at(7 << 20);
+        let mut bufs = [IoSlice::new(a), IoSlice::new(b), IoSlice::new(c)];
+        let mut bufs = &mut bufs[..];
+        while !bufs.is_empty() {
+            let n = client.write_vectored(bufs).unwrap();
+            IoSlice::advance_slices(&mut bufs, n);
+        }
+        client
+    });
+    // The sockets fill up while nothing is read, the frames being left
+    // halfway written.
+    std::thread::sleep(Duration::from_millis(200));
+    let mut received = vec![0u8; data.len()];
+    server.read_exact(&mut received).unwrap();
+    let client = handle.join().unwrap();
+    assert!(received == data, "the bytes received differ from the ones sent");
+    let frames = data.len().div_ceil(8 * 1024) as u64;
+    assert!(client.io_counters().writes > frames, "{:?}", client.io_counters());
+}